@@ -1,10 +1,16 @@
 use std::env;
 
+use crate::bandwidth;
+
 pub struct Args
 {
 	pub wait_to_exit: bool,
 	pub log_level: LogLevel,
 	pub continue_on_error: bool,
+	pub dry_run: bool, //Only print what would be done, without a backup, transfers or saving anything
+	pub review: bool, //Show the plan in the TUI so it can be changed before syncing
+	pub upload_limit: Option<u64>, //Bytes per second, for every remote
+	pub download_limit: Option<u64>,
 }
 
 #[derive(PartialEq)]
@@ -21,6 +27,10 @@ pub fn check_arguments() -> Args
 		wait_to_exit: false,
 		log_level: LogLevel::Default,
 		continue_on_error: false,
+		dry_run: false,
+		review: false,
+		upload_limit: None,
+		download_limit: None,
 	};
 
 	let args: Vec<String> = env::args().collect();
@@ -39,10 +49,41 @@ pub fn check_arguments() -> Args
 			"--continue-on-error" =>
 			{
 				result.continue_on_error = true;
+			},
+			"--dry-run" =>
+			{
+				result.dry_run = true;
+			},
+			"--review" =>
+			{
+				result.review = true;
+			},
+			_ =>
+			{
+				if let Some(value) = arg.strip_prefix("--upload-limit=")
+				{
+					result.upload_limit = Some(get_rate_argument("--upload-limit", value));
+				}
+				if let Some(value) = arg.strip_prefix("--download-limit=")
+				{
+					result.download_limit = Some(get_rate_argument("--download-limit", value));
+				}
 			}
-			_ => ()
 		}
 	}
 
 	result
-}
\ No newline at end of file
+}
+
+fn get_rate_argument(name: &str, value: &str) -> u64
+{
+	match bandwidth::parse_rate(value)
+	{
+		Some(value) => value,
+		None =>
+		{
+			println!("[ERROR] {name} should be a rate in bytes per second, like 500K or 2M! (got \"{value}\")");
+			std::process::exit(1);
+		}
+	}
+}
@@ -0,0 +1,234 @@
+use std::{io::{self, Read, Write}, sync::Mutex, thread, time::{Duration, Instant}};
+
+use crate::{args::Args, config::SyncLocation, progress};
+
+//Spaces out transfers so they don't go faster than the limit, it's shared by every connection transferring in the same direction.
+pub struct RateLimiter
+{
+	bytes_per_second: u64,
+	next_free: Mutex<Instant>, //When the bytes already let through would have finished at the limit
+}
+
+impl RateLimiter
+{
+	pub fn new(bytes_per_second: u64) -> Self
+	{
+		Self
+		{
+			bytes_per_second,
+			next_free: Mutex::new(Instant::now()),
+		}
+	}
+
+	pub fn get_bytes_per_second(&self) -> u64
+	{
+		self.bytes_per_second
+	}
+
+	//Small chunks keep slow limits smooth instead of sending a whole buffer and then waiting for seconds
+	fn get_chunk_size(&self) -> usize
+	{
+		(self.bytes_per_second / 10).clamp(1024, 64 * 1024) as usize
+	}
+
+	fn wait_for(&self, bytes: usize)
+	{
+		let duration = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+		let wait_until = match self.next_free.lock()
+		{
+			Ok(mut next_free) =>
+			{
+				//Time spent idle isn't saved up for later
+				let start = (*next_free).max(Instant::now());
+				*next_free = start + duration;
+				*next_free
+			},
+			Err(_) => return,
+		};
+
+		let now = Instant::now();
+		if wait_until > now
+		{
+			thread::sleep(wait_until - now);
+		}
+	}
+}
+
+//Wraps what put_file and append_file read from.
+pub struct LimitedReader<'a, R: Read>
+{
+	inner: R,
+	limiter: Option<&'a RateLimiter>,
+}
+
+impl<'a, R: Read> LimitedReader<'a, R>
+{
+	pub fn new(inner: R, limiter: Option<&'a RateLimiter>) -> Self
+	{
+		Self
+		{
+			inner,
+			limiter,
+		}
+	}
+}
+
+impl<R: Read> Read for LimitedReader<'_, R>
+{
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>
+	{
+		let limiter = match self.limiter
+		{
+			Some(value) => value,
+			None => return self.inner.read(buffer),
+		};
+
+		let length = buffer.len().min(limiter.get_chunk_size());
+		match self.inner.read(&mut buffer[..length])
+		{
+			Ok(read) =>
+			{
+				limiter.wait_for(read);
+				Ok(read)
+			},
+			Err(error) => Err(error),
+		}
+	}
+}
+
+//Wraps what downloads are written to.
+pub struct LimitedWriter<'a, W: Write>
+{
+	inner: W,
+	limiter: Option<&'a RateLimiter>,
+}
+
+impl<'a, W: Write> LimitedWriter<'a, W>
+{
+	pub fn new(inner: W, limiter: Option<&'a RateLimiter>) -> Self
+	{
+		Self
+		{
+			inner,
+			limiter,
+		}
+	}
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W>
+{
+	fn write(&mut self, buffer: &[u8]) -> io::Result<usize>
+	{
+		let limiter = match self.limiter
+		{
+			Some(value) => value,
+			None => return self.inner.write(buffer),
+		};
+
+		let length = buffer.len().min(limiter.get_chunk_size());
+		match self.inner.write(&buffer[..length])
+		{
+			Ok(written) =>
+			{
+				limiter.wait_for(written);
+				Ok(written)
+			},
+			Err(error) => Err(error),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()>
+	{
+		self.inner.flush()
+	}
+}
+
+//The limits a sync ends up with, the lower one wins when both the command line and the remote set one.
+pub struct TransferLimits
+{
+	pub upload: Option<RateLimiter>,
+	pub download: Option<RateLimiter>,
+}
+
+impl TransferLimits
+{
+	pub fn new(sync_location: &SyncLocation, args: &Args) -> Self
+	{
+		Self
+		{
+			upload: get_lowest_limit(args.upload_limit, sync_location.upload_limit).map(RateLimiter::new),
+			download: get_lowest_limit(args.download_limit, sync_location.download_limit).map(RateLimiter::new),
+		}
+	}
+}
+
+fn get_lowest_limit(a: Option<u64>, b: Option<u64>) -> Option<u64>
+{
+	match (a, b)
+	{
+		(Some(a), Some(b)) => Some(a.min(b)),
+		(Some(value), None) | (None, Some(value)) => Some(value),
+		(None, None) => None,
+	}
+}
+
+//"500K", "2M", "1.5G" or just bytes, always per second. K is 1024 bytes.
+pub fn parse_rate(value: &str) -> Option<u64>
+{
+	let value = value.trim();
+	let (number, multiplier) = match value.chars().last()
+	{
+		Some('K') | Some('k') => (&value[..value.len() - 1], 1024.0),
+		Some('M') | Some('m') => (&value[..value.len() - 1], 1024.0 * 1024.0),
+		Some('G') | Some('g') => (&value[..value.len() - 1], 1024.0 * 1024.0 * 1024.0),
+		_ => (value, 1.0),
+	};
+
+	match number.trim().parse::<f64>()
+	{
+		Ok(number) if number.is_finite() && number * multiplier >= 1.0 => Some((number * multiplier) as u64),
+		_ => None,
+	}
+}
+
+pub fn format_rate(bytes_per_second: u64) -> String
+{
+	format!("{}/s", progress::format_size(bytes_per_second))
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn rates_with_units()
+	{
+		assert_eq!(parse_rate("500K"), Some(500 * 1024));
+		assert_eq!(parse_rate("500k"), Some(500 * 1024));
+		assert_eq!(parse_rate("1.5M"), Some(1536 * 1024));
+		assert_eq!(parse_rate("2G"), Some(2 * 1024 * 1024 * 1024));
+		assert_eq!(parse_rate(" 2M "), Some(2 * 1024 * 1024));
+		assert_eq!(parse_rate("100"), Some(100));
+	}
+
+	#[test]
+	fn rates_below_a_byte_are_invalid()
+	{
+		assert_eq!(parse_rate("0"), None);
+		assert_eq!(parse_rate("0K"), None);
+		assert_eq!(parse_rate("0.5"), None);
+		assert_eq!(parse_rate("-1M"), None);
+	}
+
+	#[test]
+	fn garbage_is_invalid()
+	{
+		assert_eq!(parse_rate(""), None);
+		assert_eq!(parse_rate("fast"), None);
+		assert_eq!(parse_rate("K"), None);
+		assert_eq!(parse_rate("500KB"), None);
+		assert_eq!(parse_rate("inf"), None);
+		assert_eq!(parse_rate("NaN"), None);
+	}
+}
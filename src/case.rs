@@ -0,0 +1,129 @@
+use std::{borrow::Cow, collections::HashMap};
+
+//Windows and macOS don't tell apart names that only differ in case, so two files the server keeps apart can end up being the same file there.
+
+//On by default where the filesystem usually doesn't care about case
+pub const CASE_INSENSITIVE_BY_DEFAULT: bool = cfg!(any(windows, target_os = "macos"));
+
+//What two paths have in common if a case-insensitive filesystem would see them as the same file
+pub fn fold_case(path: &str) -> String
+{
+	path.to_lowercase()
+}
+
+//How files are matched between both sides, borrowed when nothing would change so most paths don't have to be copied
+pub fn get_matching_key(path: &str, case_insensitive: bool) -> Cow<'_, str>
+{
+	if case_insensitive && has_case(path)
+	{
+		Cow::Owned(fold_case(path))
+	}
+	else
+	{
+		Cow::Borrowed(path)
+	}
+}
+
+//False if folding the case wouldn't change anything, letters that aren't lowercase are the only ones that change
+fn has_case(path: &str) -> bool
+{
+	path.chars().any(|character| character.is_alphabetic() && !character.is_lowercase())
+}
+
+//Groups of paths that would be the same file on a case-insensitive filesystem, each one in the order its paths were given.
+//Paths that don't collide with any other aren't returned, and a path given twice is only counted once.
+pub fn find_case_collisions<'a, I: IntoIterator<Item = &'a str>>(paths: I) -> Vec<Vec<&'a str>>
+{
+	//The first path seen with each key and its group, groups are only made once something collides
+	let mut first_paths: HashMap<Cow<'a, str>, (&'a str, Option<usize>)> = HashMap::new();
+	let mut collisions: Vec<Vec<&'a str>> = Vec::new();
+
+	for path in paths
+	{
+		let key = get_matching_key(path, true);
+		match first_paths.get_mut(&key)
+		{
+			Some((first_path, group)) =>
+			{
+				match group
+				{
+					Some(index) =>
+					{
+						if !collisions[*index].contains(&path)
+						{
+							collisions[*index].push(path);
+						}
+					},
+					None =>
+					{
+						if *first_path != path
+						{
+							*group = Some(collisions.len());
+							collisions.push(vec![*first_path, path]);
+						}
+					}
+				}
+			},
+			None =>
+			{
+				first_paths.insert(key, (path, None));
+			}
+		}
+	}
+
+	collisions
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn same_side_collision()
+	{
+		assert_eq!(find_case_collisions(["/A.txt", "/a.txt"]), vec![vec!["/A.txt", "/a.txt"]]);
+	}
+
+	#[test]
+	fn no_collision_without_case_differences()
+	{
+		assert!(find_case_collisions(["/a.txt", "/b.txt", "/a.txt"]).is_empty());
+	}
+
+	#[test]
+	fn collision_groups_keep_their_order()
+	{
+		let collisions = find_case_collisions(["/Save.dat", "/other", "/save.dat", "/SAVE.dat", "/save.dat"]);
+		assert_eq!(collisions, vec![vec!["/Save.dat", "/save.dat", "/SAVE.dat"]]);
+	}
+
+	#[test]
+	fn opposite_sides_match_only_when_case_insensitive()
+	{
+		assert_eq!(get_matching_key("/A.txt", true), get_matching_key("/a.txt", true));
+		assert_ne!(get_matching_key("/A.txt", false), get_matching_key("/a.txt", false));
+	}
+
+	#[test]
+	fn matching_key_is_borrowed_when_nothing_changes()
+	{
+		assert!(matches!(get_matching_key("/already/lower.txt", true), Cow::Borrowed(_)));
+		assert!(matches!(get_matching_key("/Upper.txt", false), Cow::Borrowed(_)));
+		assert!(matches!(get_matching_key("/Upper.txt", true), Cow::Owned(_)));
+	}
+
+	#[test]
+	fn directory_and_file_collide()
+	{
+		//A directory "Docs" and a file "docs" can't both exist where case doesn't matter
+		assert_eq!(find_case_collisions(["/Docs", "/Docs/notes.txt", "/docs"]), vec![vec!["/Docs", "/docs"]]);
+	}
+
+	#[test]
+	fn folds_non_ascii_letters()
+	{
+		assert_eq!(fold_case("/ÉTÉ/Ñandú.TXT"), "/été/ñandú.txt");
+		assert_eq!(get_matching_key("/Été", true), get_matching_key("/éTÉ", true));
+	}
+}
@@ -1,6 +1,8 @@
 use std::{fs, env};
 use serde_derive::Serialize;
 
+use crate::{bandwidth, case};
+
 #[derive(Clone, Serialize)]
 pub struct SyncLocation
 {
@@ -12,6 +14,222 @@ pub struct SyncLocation
 	pub remote_username: String,
 	pub remote_password: String,
 	pub advanced_backups: bool,
+	pub conflict_policy: ConflictPolicy,
+	pub compare_mode: CompareMode,
+	pub direction: SyncDirection,
+	pub exclude: Vec<String>, //gitignore-style patterns, added to the ones in .syncignore
+	pub include: Vec<String>, //Brings back files that exclude or .syncignore left out
+	pub symlinks: SymlinkPolicy,
+	pub connections: usize, //How many files are transferred at the same time, each over its own connection
+	pub timezone_offset: TimezoneOffset,
+	pub upload_limit: Option<u64>, //Bytes per second, None for no limit
+	pub download_limit: Option<u64>,
+	pub retries: u32, //How many more times a transfer or listing is tried when the connection drops or the server asks to come back later
+	pub case_insensitive: bool, //Files whose names only differ in case are the same file, like on Windows and macOS
+	pub unicode_normalization: NormalizationForm, //How names are normalized before matching them between both sides
+}
+
+//What to do with a file that changed on both sides since the last sync.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy
+{
+	PreferLocal,
+	PreferRemote,
+	NewestWins,
+	KeepBoth, //The older version is renamed to name.conflict-<host>-<date> and both are synced
+	Ask,
+}
+
+impl ConflictPolicy
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		match value
+		{
+			"prefer-local" => Some(Self::PreferLocal),
+			"prefer-remote" => Some(Self::PreferRemote),
+			"newest-wins" => Some(Self::NewestWins),
+			"keep-both" => Some(Self::KeepBoth),
+			"ask" => Some(Self::Ask),
+			_ => None,
+		}
+	}
+
+	pub fn to_config_value(self) -> &'static str
+	{
+		match self
+		{
+			Self::PreferLocal => "prefer-local",
+			Self::PreferRemote => "prefer-remote",
+			Self::NewestWins => "newest-wins",
+			Self::KeepBoth => "keep-both",
+			Self::Ask => "ask",
+		}
+	}
+}
+
+//What has to match for a file to be considered the same on both sides.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode
+{
+	Mtime,
+	MtimeSize,
+	Checksum, //Hashes the files that have the same size, asking the server for its hashes when it can
+}
+
+impl CompareMode
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		match value
+		{
+			"mtime" => Some(Self::Mtime),
+			"mtime-size" => Some(Self::MtimeSize),
+			"checksum" => Some(Self::Checksum),
+			_ => None,
+		}
+	}
+}
+
+//Which way changes are allowed to go.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncDirection
+{
+	Bidirectional,
+	UploadOnly, //Local changes are uploaded, nothing local is ever changed and nothing is deleted on the remote
+	DownloadOnly, //Remote changes are downloaded, nothing on the remote is ever changed and nothing is deleted locally
+	MirrorUp, //The remote is made identical to the local directory, deleting whatever isn't local
+	MirrorDown, //The local directory is made identical to the remote, deleting whatever isn't on the remote
+}
+
+impl SyncDirection
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		match value
+		{
+			"bidirectional" => Some(Self::Bidirectional),
+			"upload-only" => Some(Self::UploadOnly),
+			"download-only" => Some(Self::DownloadOnly),
+			"mirror-up" => Some(Self::MirrorUp),
+			"mirror-down" => Some(Self::MirrorDown),
+			_ => None,
+		}
+	}
+}
+
+//What to do with symbolic links found in the local directory.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy
+{
+	Skip, //Left out with a warning
+	Follow, //Synced as whatever they point to, links to directories already being synced are skipped to avoid loops
+	Preserve, //Synced as links, the server has to support SITE SYMLINK
+}
+
+impl SymlinkPolicy
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		match value
+		{
+			"skip" => Some(Self::Skip),
+			"follow" => Some(Self::Follow),
+			"preserve" => Some(Self::Preserve),
+			_ => None,
+		}
+	}
+}
+
+//The same accented name can be written with a single character (NFC, what most systems use) or as a letter followed by the accent (NFD, what macOS
+//used to use). Names are only normalized to match them, files keep the name they have on each side.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NormalizationForm
+{
+	Nfc,
+	Nfd,
+	None, //Names have to be exactly the same
+}
+
+impl NormalizationForm
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		match value
+		{
+			"nfc" => Some(Self::Nfc),
+			"nfd" => Some(Self::Nfd),
+			"none" => Some(Self::None),
+			_ => None,
+		}
+	}
+}
+
+//How far the dates in LIST are from UTC, MLSD and MDTM always use UTC.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimezoneOffset
+{
+	Auto, //Found at the start of each sync by uploading a file and reading its date back
+	Fixed(i64), //In minutes, "+02:00" in the config
+}
+
+impl TimezoneOffset
+{
+	pub fn from_config_value(value: &str) -> Option<Self>
+	{
+		if value == "auto"
+		{
+			return Some(Self::Auto);
+		}
+
+		let (sign, value) = match value.chars().next()
+		{
+			Some('+') => (1, &value[1..]),
+			Some('-') => (-1, &value[1..]),
+			_ => return None,
+		};
+
+		let (hours, minutes) = match value.split_once(':')
+		{
+			Some(value) => value,
+			None => (value, "0"),
+		};
+
+		//parse() would take another sign, like in "++02:00", so only digits are left after the one sign
+		if [hours, minutes].iter().any(|part| part.is_empty() || !part.chars().all(|character| character.is_ascii_digit()))
+		{
+			return None;
+		}
+
+		match (hours.parse::<i64>(), minutes.parse::<i64>())
+		{
+			(Ok(hours), Ok(minutes)) if (0..=14).contains(&hours) && (0..60).contains(&minutes) => Some(Self::Fixed(sign * (hours * 60 + minutes))),
+			_ => None,
+		}
+	}
+
+	pub fn to_config_value(self) -> String
+	{
+		match self
+		{
+			Self::Auto => String::from("auto"),
+			Self::Fixed(minutes) => format!("{}{:02}:{:02}", if minutes < 0 { '-' } else { '+' }, minutes.abs() / 60, minutes.abs() % 60),
+		}
+	}
+}
+
+//Saved the same way it's written in the config
+impl serde::Serialize for TimezoneOffset
+{
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	{
+		serializer.serialize_str(&self.to_config_value())
+	}
 }
 
 pub fn get_program_folder() -> String
@@ -93,7 +311,7 @@ fn read_config_file_as_json() -> Option<serde_json::Value>
 		}
 	};
 
-	let json: serde_json::Value = match serde_json::from_str(&config_file.as_str())
+	let json: serde_json::Value = match serde_json::from_str(config_file.as_str())
 	{
 		Ok(value) => value,
 		Err(error) =>
@@ -108,11 +326,7 @@ fn read_config_file_as_json() -> Option<serde_json::Value>
 
 pub fn get_config() -> Option<Vec<SyncLocation>>
 {
-	let json = match read_config_file_as_json()
-	{
-		Some(value) => value,
-		None => return None,
-	};
+	let json = read_config_file_as_json()?;
 
 	let root = match json.as_array()
 	{
@@ -293,6 +507,106 @@ pub fn get_config() -> Option<Vec<SyncLocation>>
 					}
 				};
 
+				//Settings added after the first release are optional, so older configs keep working
+				let conflict_policy = match get_enum_field(obj.get("conflict_policy"), "conflict_policy", ConflictPolicy::NewestWins, ConflictPolicy::from_config_value, "one of \"prefer-local\", \"prefer-remote\", \"newest-wins\", \"keep-both\" or \"ask\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let compare_mode = match get_enum_field(obj.get("compare_mode"), "compare_mode", CompareMode::Mtime, CompareMode::from_config_value, "one of \"mtime\", \"mtime-size\" or \"checksum\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let direction = match get_enum_field(obj.get("direction"), "direction", SyncDirection::Bidirectional, SyncDirection::from_config_value, "one of \"bidirectional\", \"upload-only\", \"download-only\", \"mirror-up\" or \"mirror-down\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let symlinks = match get_enum_field(obj.get("symlinks"), "symlinks", SymlinkPolicy::Skip, SymlinkPolicy::from_config_value, "one of \"skip\", \"follow\" or \"preserve\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let connections = match obj.get("connections")
+				{
+					Some(value) =>
+					{
+						match value.as_u64()
+						{
+							Some(value) if value >= 1 => value as usize,
+							_ =>
+							{
+								println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - connections should be a number bigger than 0! (Please fix this, config file is located at \"{}\")", get_config_location());
+								continue;
+							}
+						}
+					},
+					None => 1,
+				};
+				let retries = match obj.get("retries")
+				{
+					Some(value) =>
+					{
+						match value.as_u64()
+						{
+							Some(value) if value <= 10 => value as u32,
+							_ =>
+							{
+								println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - retries should be a number from 0 to 10! (Please fix this, config file is located at \"{}\")", get_config_location());
+								continue;
+							}
+						}
+					},
+					None => 3,
+				};
+				let case_insensitive = match obj.get("case_insensitive")
+				{
+					Some(value) =>
+					{
+						match value.as_bool()
+						{
+							Some(value) => value,
+							None =>
+							{
+								println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - case_insensitive should be a boolean! (Please fix this, config file is located at \"{}\")", get_config_location());
+								continue;
+							}
+						}
+					},
+					None => case::CASE_INSENSITIVE_BY_DEFAULT,
+				};
+				let unicode_normalization = match get_enum_field(obj.get("unicode_normalization"), "unicode_normalization", NormalizationForm::Nfc, NormalizationForm::from_config_value, "one of \"nfc\", \"nfd\" or \"none\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let timezone_offset = match get_enum_field(obj.get("timezone_offset"), "timezone_offset", TimezoneOffset::Auto, TimezoneOffset::from_config_value, "\"auto\" or an offset like \"+02:00\"", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let upload_limit = match get_rate_limit(obj.get("upload_limit"), "upload_limit", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let download_limit = match get_rate_limit(obj.get("download_limit"), "download_limit", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let exclude = match get_pattern_list(obj.get("exclude"), "exclude", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+				let include = match get_pattern_list(obj.get("include"), "include", name)
+				{
+					Some(value) => value,
+					None => continue,
+				};
+
 				sync_locations.push
 				(
 					SyncLocation
@@ -304,7 +618,20 @@ pub fn get_config() -> Option<Vec<SyncLocation>>
 						local_path: String::from(local_path),
 						remote_username: String::from(remote_username),
 						remote_password: String::from(remote_password),
-						advanced_backups: advanced_backups,
+						advanced_backups,
+						conflict_policy,
+						compare_mode,
+						direction,
+						exclude,
+						include,
+						symlinks,
+						connections,
+						timezone_offset,
+						upload_limit,
+						download_limit,
+						retries,
+						case_insensitive,
+						unicode_normalization,
 					}
 				);
 			},
@@ -318,6 +645,99 @@ pub fn get_config() -> Option<Vec<SyncLocation>>
 	Some(sync_locations)
 }
 
+//Returns None if the field is invalid and the default if it's missing. expected says what the field should be, for the error message.
+fn get_enum_field<T>(value: Option<&serde_json::Value>, field_name: &str, default: T, parse: fn(&str) -> Option<T>, expected: &str, name: &str) -> Option<T>
+{
+	let value = match value
+	{
+		Some(value) => value,
+		None => return Some(default),
+	};
+
+	let value = match value.as_str()
+	{
+		Some(value) => value,
+		None =>
+		{
+			println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - {field_name} should be a string! (Please fix this, config file is located at \"{}\")", get_config_location());
+			return None;
+		}
+	};
+
+	match parse(value)
+	{
+		Some(value) => Some(value),
+		None =>
+		{
+			println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - {field_name} should be {expected}! (Please fix this, config file is located at \"{}\")", get_config_location());
+			None
+		}
+	}
+}
+
+//Returns None if the field is invalid and Some(None) if it's missing. Takes bytes per second as a number or a string like "500K".
+fn get_rate_limit(value: Option<&serde_json::Value>, field_name: &str, name: &str) -> Option<Option<u64>>
+{
+	let value = match value
+	{
+		Some(value) => value,
+		None => return Some(None),
+	};
+
+	let rate = match value
+	{
+		serde_json::Value::Number(number) => number.as_u64().filter(|rate| *rate > 0),
+		serde_json::Value::String(string) => bandwidth::parse_rate(string),
+		_ => None,
+	};
+
+	match rate
+	{
+		Some(value) => Some(Some(value)),
+		None =>
+		{
+			println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - {field_name} should be bytes per second, as a number or a string like \"500K\" or \"2M\"! (Please fix this, config file is located at \"{}\")", get_config_location());
+			None
+		}
+	}
+}
+
+//Returns None if the field is invalid, a missing field is just an empty list
+fn get_pattern_list(value: Option<&serde_json::Value>, field_name: &str, name: &str) -> Option<Vec<String>>
+{
+	let value = match value
+	{
+		Some(value) => value,
+		None => return Some(Vec::new()),
+	};
+
+	let array = match value.as_array()
+	{
+		Some(value) => value,
+		None =>
+		{
+			println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - {field_name} should be an array of strings! (Please fix this, config file is located at \"{}\")", get_config_location());
+			return None;
+		}
+	};
+
+	let mut patterns: Vec<String> = Vec::with_capacity(array.len());
+	for item in array
+	{
+		match item.as_str()
+		{
+			Some(value) => patterns.push(String::from(value)),
+			None =>
+			{
+				println!("[ERROR] Config error: Remote with name \"{name}\" has an invalid field! - {field_name} should be an array of strings! (Please fix this, config file is located at \"{}\")", get_config_location());
+				return None;
+			}
+		}
+	}
+
+	Some(patterns)
+}
+
 fn save_default_config() -> Option<String>
 {
 	let default_config_contents = "[]"; //Un array vacío en json
@@ -399,3 +819,61 @@ pub fn add_new_remote(remote: &SyncLocation) -> bool
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn timezone_offsets()
+	{
+		assert_eq!(TimezoneOffset::from_config_value("auto"), Some(TimezoneOffset::Auto));
+		assert_eq!(TimezoneOffset::from_config_value("+02:00"), Some(TimezoneOffset::Fixed(120)));
+		assert_eq!(TimezoneOffset::from_config_value("+05:30"), Some(TimezoneOffset::Fixed(330)));
+		assert_eq!(TimezoneOffset::from_config_value("-5"), Some(TimezoneOffset::Fixed(-300)));
+		assert_eq!(TimezoneOffset::from_config_value("-03:30"), Some(TimezoneOffset::Fixed(-210)));
+		assert_eq!(TimezoneOffset::from_config_value("+00:00"), Some(TimezoneOffset::Fixed(0)));
+		assert_eq!(TimezoneOffset::from_config_value("+14:00"), Some(TimezoneOffset::Fixed(840)));
+	}
+
+	#[test]
+	fn invalid_timezone_offsets()
+	{
+		for value in ["", "2", "02:00", "+", "+15:00", "+02:60", "+-2", "+02:-30", "+two", "Auto", "UTC"]
+		{
+			assert_eq!(TimezoneOffset::from_config_value(value), None, "{value}");
+		}
+	}
+
+	#[test]
+	fn timezone_offsets_with_more_than_one_sign()
+	{
+		for value in ["++02:00", "--02:00", "+-02:00", "-+02:00", "+02:+30", "-02:-30", "+ 02:00", "+02:"]
+		{
+			assert_eq!(TimezoneOffset::from_config_value(value), None, "{value}");
+		}
+	}
+
+	#[test]
+	fn timezone_offsets_are_written_back_the_same()
+	{
+		for value in ["auto", "+02:00", "-05:00", "+05:45", "+00:00"]
+		{
+			let offset = TimezoneOffset::from_config_value(value);
+			assert_eq!(offset.map(TimezoneOffset::to_config_value), Some(String::from(value)));
+		}
+	}
+
+	#[test]
+	fn enum_fields()
+	{
+		let expected = "one of \"mtime\", \"mtime-size\" or \"checksum\"";
+		let parse = |value: Option<&serde_json::Value>| get_enum_field(value, "compare_mode", CompareMode::Mtime, CompareMode::from_config_value, expected, "test");
+
+		assert_eq!(parse(None), Some(CompareMode::Mtime));
+		assert_eq!(parse(Some(&serde_json::json!("checksum"))), Some(CompareMode::Checksum));
+		assert_eq!(parse(Some(&serde_json::json!("sha256"))), None);
+		assert_eq!(parse(Some(&serde_json::json!(2))), None);
+	}
+}
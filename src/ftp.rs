@@ -0,0 +1,604 @@
+use std::{io, str::FromStr};
+use chrono::{DateTime, Duration, Utc};
+use suppaftp::{FtpError, FtpStream, Status, list, types::FileType};
+
+use crate::{config::SyncLocation, hash::HashAlgorithm};
+
+//Optional commands the server might support, detected through FEAT right after logging in.
+#[derive(Clone)]
+pub struct ServerFeatures
+{
+	pub mfmt: bool,
+	pub mdtm: bool,
+	pub mlsd: bool,
+	pub site_utime: bool, //FEAT doesn't advertise SITE commands, so it's assumed until the server refuses it
+	pub site_symlink: bool, //Same as site_utime
+	pub hash_command: Option<(HashCommand, HashAlgorithm)>, //How to ask the server for the hash of a file, None if it can't
+	pub list_timezone_offset: i64, //Minutes the dates in LIST are ahead of UTC, set from the config or the clock probe
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HashCommand
+{
+	Hash, //The draft-bryan-ftpext-hash HASH command, the algorithm is chosen with OPTS HASH
+	Xmd5,
+	Xcrc,
+}
+
+//Connects, logs in and switches to binary transfers like the first connection of a sync does, for the extra connections that only transfer files.
+//The hash algorithm is chosen per session, so it's chosen again too.
+pub fn open_connection(sync_location: &SyncLocation, server_features: &ServerFeatures) -> Result<FtpStream, String>
+{
+	let mut ftp_stream = match FtpStream::connect(&sync_location.remote)
+	{
+		Ok(value) => value,
+		Err(error) => return Err(format!("failed to connect to server, {error}")),
+	};
+
+	match ftp_stream.login(&sync_location.remote_username, &sync_location.remote_password)
+	{
+		Ok(_) => (),
+		Err(error) => return Err(format!("failed to log in, {error}")),
+	}
+
+	match ftp_stream.transfer_type(FileType::Binary)
+	{
+		Ok(_) => (),
+		Err(error) => return Err(format!("failed to switch to binary transfers, {error}")),
+	}
+
+	//If it fails, get_remote_hash sees the wrong algorithm in the answer and ignores it
+	if let Some((HashCommand::Hash, algorithm)) = server_features.hash_command
+	{
+		let _ = ftp_stream.opts("HASH", Some(algorithm.name()));
+	}
+
+	Ok(ftp_stream)
+}
+
+//Replaces a connection that was lost with a new one. The old one is just dropped, saying goodbye could hang if it's half open.
+pub fn reconnect(ftp_stream: &mut FtpStream, sync_location: &SyncLocation, server_features: &ServerFeatures) -> Result<(), String>
+{
+	match open_connection(sync_location, server_features)
+	{
+		Ok(value) =>
+		{
+			*ftp_stream = value;
+			Ok(())
+		},
+		Err(error) => Err(error),
+	}
+}
+
+pub fn get_server_features(ftp_stream: &mut FtpStream) -> ServerFeatures
+{
+	//Servers that don't know FEAT simply don't get any optional command used
+	let features = ftp_stream.feat().unwrap_or_default();
+
+	let mut feature_names: Vec<String> = Vec::with_capacity(features.len());
+	let mut hash_algorithms: Vec<String> = Vec::new();
+	for (name, value) in &features
+	{
+		let name = name.to_uppercase();
+		if name == "HASH"
+		{
+			if let Some(value) = value
+			{
+				for algorithm in value.split(';')
+				{
+					hash_algorithms.push(algorithm.trim().to_uppercase());
+				}
+			}
+		}
+		feature_names.push(name);
+	}
+
+	ServerFeatures
+	{
+		mfmt: feature_names.contains(&String::from("MFMT")),
+		mdtm: feature_names.contains(&String::from("MDTM")),
+		mlsd: feature_names.contains(&String::from("MLST")), //MLSD comes with MLST (RFC 3659)
+		site_utime: true,
+		site_symlink: true,
+		hash_command: get_hash_command(ftp_stream, &feature_names, &hash_algorithms),
+		list_timezone_offset: 0,
+	}
+}
+
+//Prefers HASH over the older XMD5 and XCRC, and MD5 over CRC32 because it's less likely to collide
+fn get_hash_command(ftp_stream: &mut FtpStream, feature_names: &[String], hash_algorithms: &[String]) -> Option<(HashCommand, HashAlgorithm)>
+{
+	for algorithm in [HashAlgorithm::Md5, HashAlgorithm::Crc32]
+	{
+		let name = algorithm.name();
+
+		//The algorithm marked with * is the one currently selected
+		if hash_algorithms.contains(&format!("{name}*"))
+		{
+			return Some((HashCommand::Hash, algorithm));
+		}
+
+		if hash_algorithms.contains(&String::from(name))
+		&& ftp_stream.opts("HASH", Some(name)).is_ok()
+		{
+			return Some((HashCommand::Hash, algorithm));
+		}
+	}
+
+	if feature_names.contains(&String::from("XMD5"))
+	{
+		return Some((HashCommand::Xmd5, HashAlgorithm::Md5));
+	}
+
+	if feature_names.contains(&String::from("XCRC"))
+	{
+		return Some((HashCommand::Xcrc, HashAlgorithm::Crc32));
+	}
+
+	None
+}
+
+//Returns the hash tagged with its algorithm (see HashAlgorithm::tag), or None if the server can't or didn't want to hash the file.
+pub fn get_remote_hash(ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, remote_fullpath: &str) -> Option<String>
+{
+	let (hash_command, algorithm) = server_features.hash_command?;
+
+	//HASH answers "213 MD5 0-1234 <hash> <path>", XMD5 and XCRC answer "250 <hash>"
+	let (command, hash_position) = match hash_command
+	{
+		HashCommand::Hash => (format!("HASH {remote_fullpath}"), 3),
+		HashCommand::Xmd5 => (format!("XMD5 {remote_fullpath}"), 1),
+		HashCommand::Xcrc => (format!("XCRC {remote_fullpath}"), 1),
+	};
+
+	let response = match ftp_stream.custom_command(command, &[Status::File, Status::RequestedFileActionOk])
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			if is_not_implemented(&error)
+			{
+				server_features.hash_command = None;
+			}
+			return None;
+		}
+	};
+
+	let body = String::from_utf8_lossy(&response.body).to_string();
+	let hash = body.split_whitespace().nth(hash_position)?;
+
+	//A session that didn't take OPTS HASH answers with the server's default algorithm, which would never match
+	if hash_command == HashCommand::Hash && !body.split_whitespace().nth(1)?.eq_ignore_ascii_case(algorithm.name())
+	{
+		return None;
+	}
+
+	if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit())
+	{
+		return None;
+	}
+
+	//Some servers drop the leading zeros of CRC32
+	match algorithm
+	{
+		HashAlgorithm::Crc32 => Some(algorithm.tag(&format!("{:0>8}", hash))),
+		HashAlgorithm::Md5 => Some(algorithm.tag(hash)),
+	}
+}
+
+//MDTM answers with the exact date in UTC, unlike LIST which only has minutes (or days, for old files) in the server's timezone.
+pub fn get_remote_date_modified_exact(ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, remote_fullpath: &str) -> Option<DateTime<Utc>>
+{
+	if !server_features.mdtm
+	{
+		return None;
+	}
+
+	match ftp_stream.mdtm(remote_fullpath)
+	{
+		Ok(value) => Some(value.and_utc()),
+		Err(error) =>
+		{
+			if is_not_implemented(&error)
+			{
+				server_features.mdtm = false;
+			}
+			None
+		}
+	}
+}
+
+//Tries MFMT first and SITE UTIME after that, returns false if the server doesn't allow changing the modified date.
+pub fn set_remote_date_modified(ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, remote_fullpath: &str, date_modified: &DateTime<Utc>) -> bool
+{
+	let timestamp = date_modified.format("%Y%m%d%H%M%S").to_string();
+
+	if server_features.mfmt
+	{
+		match ftp_stream.custom_command(format!("MFMT {timestamp} {remote_fullpath}"), &[Status::File])
+		{
+			Ok(_) => return true,
+			Err(error) =>
+			{
+				if is_not_implemented(&error)
+				{
+					server_features.mfmt = false;
+				}
+			}
+		}
+	}
+
+	if server_features.site_utime
+	{
+		match ftp_stream.site(format!("UTIME {timestamp} {remote_fullpath}"))
+		{
+			Ok(_) => return true,
+			Err(error) =>
+			{
+				if is_not_implemented(&error)
+				{
+					server_features.site_utime = false;
+				}
+			}
+		}
+	}
+
+	false
+}
+
+//Returns Ok(false) if the server doesn't support SITE SYMLINK.
+pub fn create_remote_symlink(ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, target: &str, remote_fullpath: &str) -> Result<bool, FtpError>
+{
+	if !server_features.site_symlink
+	{
+		return Ok(false);
+	}
+
+	match ftp_stream.site(format!("SYMLINK {target} {remote_fullpath}"))
+	{
+		Ok(_) => Ok(true),
+		Err(error) =>
+		{
+			if is_not_implemented(&error)
+			{
+				server_features.site_symlink = false;
+				return Ok(false);
+			}
+			Err(error)
+		}
+	}
+}
+
+//LIST dates are in the server's timezone, this turns them into UTC
+pub fn get_list_date_modified(ftp_file: &list::File, server_features: &ServerFeatures) -> DateTime<Utc>
+{
+	let date_modified: DateTime<Utc> = ftp_file.modified().into();
+	date_modified - Duration::minutes(server_features.list_timezone_offset)
+}
+
+//What uploading a file and reading its date back says about the server.
+pub struct ClockProbe
+{
+	pub list_timezone_offset: i64, //In minutes, rounded to 15 like timezones are
+	pub clock_skew: Option<i64>, //Seconds the server's clock is ahead of this one, only known if the server supports MDTM
+}
+
+//The marker file is removed right after, LIST is asked for it directly so it doesn't matter if the server hides dotfiles.
+pub fn probe_server_clock(ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, marker_fullpath: &str) -> Result<ClockProbe, String>
+{
+	let before_upload = Utc::now();
+	match ftp_stream.put_file(marker_fullpath, &mut "sync-remote clock probe\n".as_bytes())
+	{
+		Ok(_) => (),
+		Err(error) => return Err(format!("failed to upload {marker_fullpath}, {error}")),
+	}
+	let after_upload = Utc::now();
+	let local_date = before_upload + (after_upload - before_upload) / 2;
+
+	let exact_date = get_remote_date_modified_exact(ftp_stream, server_features, marker_fullpath);
+	let listing = ftp_stream.list(Some(marker_fullpath));
+	let _ = ftp_stream.rm(marker_fullpath);
+
+	let listing = match listing
+	{
+		Ok(value) => value,
+		Err(error) => return Err(format!("failed to list {marker_fullpath}, {error}")),
+	};
+
+	let mut list_date: Option<DateTime<Utc>> = None;
+	for item in listing
+	{
+		match list::File::from_str(item.as_str())
+		{
+			Ok(value) if value.is_file() =>
+			{
+				list_date = Some(value.modified().into());
+				break;
+			},
+			_ => continue,
+		}
+	}
+	let list_date = match list_date
+	{
+		Some(value) => value,
+		None => return Err(format!("the server didn't list {marker_fullpath}")),
+	};
+
+	//Against the server's own clock if it can be asked for it, so a wrong clock doesn't look like a different timezone
+	let list_offset = (list_date - exact_date.unwrap_or(local_date)).num_seconds();
+	let quarters_of_hour = (list_offset as f64 / (15.0 * 60.0)).round() as i64;
+
+	Ok
+	(
+		ClockProbe
+		{
+			list_timezone_offset: quarters_of_hour * 15,
+			clock_skew: exact_date.map(|exact_date| (exact_date - local_date).num_seconds()),
+		}
+	)
+}
+
+//SIZE and MDTM only answer for files that exist. None if the server has neither or the answer didn't say.
+pub fn remote_file_exists(ftp_stream: &mut FtpStream, remote_fullpath: &str) -> Option<bool>
+{
+	let error = match ftp_stream.size(remote_fullpath)
+	{
+		Ok(_) => return Some(true),
+		Err(error) => error,
+	};
+
+	let error = if is_not_implemented(&error)
+	{
+		match ftp_stream.mdtm(remote_fullpath)
+		{
+			Ok(_) => return Some(true),
+			Err(error) => error,
+		}
+	}
+	else
+	{
+		error
+	};
+
+	match error
+	{
+		FtpError::UnexpectedResponse(response) if response.status == Status::FileUnavailable => Some(false),
+		_ => None,
+	}
+}
+
+//True if the server answered that it doesn't know the command, instead of failing to do it
+pub fn is_not_implemented(error: &FtpError) -> bool
+{
+	match error
+	{
+		FtpError::UnexpectedResponse(response) =>
+		{
+			response.status == Status::BadCommand
+			|| response.status == Status::NotImplemented
+			|| response.status == Status::NotImplementedParameter
+			|| response.status == Status::CommandNotImplemented
+		},
+		_ => false,
+	}
+}
+
+//True for failures that might not happen again if the same thing is tried a bit later on a new connection: the connection dropped
+//or timed out, or the server is busy and asked to come back later
+pub fn is_transient_error(error: &FtpError) -> bool
+{
+	match error
+	{
+		FtpError::ConnectionError(error) => is_transient_io_error(error),
+		FtpError::UnexpectedResponse(response) =>
+		{
+			response.status == Status::NotAvailable
+			|| response.status == Status::CannotOpenDataConnection
+			|| response.status == Status::TransferAborted
+			|| response.status == Status::RequestFileActionIgnored
+			|| response.status == Status::ActionAborted
+		},
+		FtpError::BadResponse => true, //Usually a reply cut in half
+		_ => false,
+	}
+}
+
+pub fn is_transient_io_error(error: &io::Error) -> bool
+{
+	matches!
+	(
+		error.kind(),
+		io::ErrorKind::TimedOut
+		| io::ErrorKind::WouldBlock
+		| io::ErrorKind::Interrupted
+		| io::ErrorKind::BrokenPipe
+		| io::ErrorKind::ConnectionRefused //The data connection, when the server dropped the session before it was opened
+		| io::ErrorKind::ConnectionReset
+		| io::ErrorKind::ConnectionAborted
+		| io::ErrorKind::NotConnected
+		| io::ErrorKind::UnexpectedEof
+	)
+}
+
+//Returns None for the entries that aren't files, directories or links (the directory itself, its parent, devices...)
+pub fn parse_mlsd_line(line: &str) -> Option<Result<list::File, list::ParseError>>
+{
+	//Facts come before the first space, the name is everything after it
+	let (facts, name) = match line.split_once(' ')
+	{
+		Some(value) => value,
+		None => return Some(Err(list::ParseError::SyntaxError)),
+	};
+
+	let mut normalized_facts: Vec<String> = Vec::new();
+	for fact in facts.split(';')
+	{
+		let (key, value) = match fact.split_once('=')
+		{
+			Some(value) => value,
+			None => continue,
+		};
+
+		match key.to_lowercase().as_str()
+		{
+			"type" =>
+			{
+				match value.to_lowercase().as_str()
+				{
+					"file" | "dir" => (),
+					//Servers that don't show links as what they point to say they're links in different ways
+					"os.unix=symlink" | "link" =>
+					{
+						normalized_facts.push(String::from("type=link"));
+						continue;
+					},
+					other if other.starts_with("os.unix=slink") =>
+					{
+						normalized_facts.push(String::from("type=link"));
+						continue;
+					},
+					_ => return None,
+				}
+			},
+			//Some servers (like IIS) add milliseconds, which suppaftp doesn't understand. Dates are compared in seconds anyway.
+			"modify" if value.contains('.') =>
+			{
+				match value.split_once('.')
+				{
+					Some((seconds, _)) => normalized_facts.push(format!("{key}={seconds}")),
+					None => normalized_facts.push(format!("{key}={value}")),
+				}
+				continue;
+			},
+			//suppaftp only understands 3 digits, but lots of servers send 4 (0644)
+			"unix.mode" if value.len() > 3 =>
+			{
+				normalized_facts.push(format!("{key}={}", &value[value.len() - 3..]));
+				continue;
+			},
+			_ => (),
+		}
+		normalized_facts.push(format!("{key}={value}"));
+	}
+
+	Some(list::File::from_mlsx_line(&format!("{}; {}", normalized_facts.join(";"), name)))
+}
+
+//Only servers that say links are "OS.unix=slink:target" tell where they point to
+pub fn get_mlsd_line_symlink_target(line: &str) -> Option<String>
+{
+	let (facts, _) = line.split_once(' ')?;
+
+	for fact in facts.split(';')
+	{
+		match fact.split_once('=')
+		{
+			Some((key, value)) if key.eq_ignore_ascii_case("type") =>
+			{
+				return match value.split_once(':')
+				{
+					Some((kind, target)) if kind.eq_ignore_ascii_case("os.unix=slink") && !target.is_empty() => Some(target.to_string()),
+					_ => None,
+				};
+			},
+			_ => (),
+		}
+	}
+	None
+}
+
+pub fn mlsd_line_has_date(line: &str) -> bool
+{
+	match line.split_once(' ')
+	{
+		Some((facts, _)) => facts.to_lowercase().contains("modify="),
+		None => false,
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	//2024-03-15 10:30:00 UTC
+	const MODIFIED: i64 = 1_710_498_600;
+
+	fn parse(line: &str) -> list::File
+	{
+		match parse_mlsd_line(line)
+		{
+			Some(Ok(value)) => value,
+			Some(Err(error)) => panic!("{line} failed to parse, {error:?}"),
+			None => panic!("{line} was skipped"),
+		}
+	}
+
+	fn get_timestamp(file: &list::File) -> i64
+	{
+		DateTime::<Utc>::from(file.modified()).timestamp()
+	}
+
+	#[test]
+	fn proftpd_lines()
+	{
+		let file = parse("modify=20240315103000;perm=adfrw;size=1234;type=file;unique=FD00U1A2B3;UNIX.group=1000;UNIX.groupname=user;UNIX.mode=0644;UNIX.owner=1000;UNIX.ownername=user; notes.txt");
+		assert_eq!(file.name(), "notes.txt");
+		assert!(file.is_file());
+		assert_eq!(file.size(), 1234);
+		assert_eq!(get_timestamp(&file), MODIFIED);
+
+		let line = "modify=20240315103000;perm=adfrw;size=11;type=OS.unix=slink:/home/user/target.txt;unique=FD00U1A2B4;UNIX.mode=0777;UNIX.owner=1000; link.txt";
+		let file = parse(line);
+		assert_eq!(file.name(), "link.txt");
+		assert!(file.is_symlink());
+		assert_eq!(get_mlsd_line_symlink_target(line), Some(String::from("/home/user/target.txt")));
+
+		//The directory itself and its parent
+		assert!(parse_mlsd_line("modify=20240315103000;perm=flcdmpe;type=cdir;unique=FD00U1A2B5;UNIX.mode=0755; .").is_none());
+		assert!(parse_mlsd_line("modify=20240315103000;perm=flcdmpe;type=pdir;unique=FD00U1A2B6;UNIX.mode=0755; ..").is_none());
+	}
+
+	#[test]
+	fn pure_ftpd_lines()
+	{
+		let file = parse("type=file;size=1234;modify=20240315103000;UNIX.mode=0644;UNIX.uid=1000;UNIX.gid=1000;unique=803g2a1; my notes.txt");
+		assert_eq!(file.name(), "my notes.txt");
+		assert!(file.is_file());
+		assert_eq!(get_timestamp(&file), MODIFIED);
+
+		let file = parse("type=dir;sizd=4096;modify=20240315103000;UNIX.mode=0755;UNIX.uid=1000;UNIX.gid=1000;unique=803g2a2; docs");
+		assert_eq!(file.name(), "docs");
+		assert!(file.is_directory());
+	}
+
+	#[test]
+	fn facts_are_case_insensitive_and_can_have_milliseconds()
+	{
+		//Like IIS sends them
+		let file = parse("Type=file;Size=1234;Modify=20240315103000.123;Create=20240101000000.000; notes.txt");
+		assert_eq!(file.name(), "notes.txt");
+		assert!(file.is_file());
+		assert_eq!(file.size(), 1234);
+		assert_eq!(get_timestamp(&file), MODIFIED);
+
+		let file = parse("TYPE=DIR;MODIFY=20240315103000; Docs");
+		assert!(file.is_directory());
+	}
+
+	#[test]
+	fn links_without_a_target()
+	{
+		let line = "type=OS.unix=symlink;size=11;modify=20240315103000; link";
+		assert!(parse(line).is_symlink());
+		assert_eq!(get_mlsd_line_symlink_target(line), None);
+		assert!(parse("type=link;modify=20240315103000; link").is_symlink());
+	}
+
+	#[test]
+	fn devices_and_broken_lines()
+	{
+		assert!(parse_mlsd_line("type=OS.unix=chr-13/29;modify=20240315103000; null").is_none());
+		assert!(matches!(parse_mlsd_line("type=file;size=1"), Some(Err(_))));
+	}
+}
@@ -0,0 +1,259 @@
+use std::{fs, collections::HashMap, io::Read};
+use md5::{Md5, Digest};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::config::{SyncLocation, get_program_folder};
+
+//Only the algorithms FTP servers commonly offer through HASH, XMD5 and XCRC.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HashAlgorithm
+{
+	Md5,
+	Crc32,
+}
+
+impl HashAlgorithm
+{
+	pub fn name(&self) -> &'static str
+	{
+		match self
+		{
+			HashAlgorithm::Md5 => "MD5",
+			HashAlgorithm::Crc32 => "CRC32",
+		}
+	}
+
+	//Hashes are stored and compared as "algorithm:hex", so hashes made with different algorithms never match
+	pub fn tag(&self, hex: &str) -> String
+	{
+		format!("{}:{}", self.name().to_lowercase(), hex.to_lowercase())
+	}
+
+	pub fn from_tagged_hash(hash: &str) -> Option<Self>
+	{
+		match hash.split_once(':')
+		{
+			Some(("md5", _)) => Some(HashAlgorithm::Md5),
+			Some(("crc32", _)) => Some(HashAlgorithm::Crc32),
+			_ => None,
+		}
+	}
+}
+
+//None if there's nothing to compare, either because a hash is missing or because they were made with different algorithms
+pub fn compare_hashes(a: &Option<String>, b: &Option<String>) -> Option<bool>
+{
+	match (a, b)
+	{
+		(Some(a), Some(b)) =>
+		{
+			if HashAlgorithm::from_tagged_hash(a) == HashAlgorithm::from_tagged_hash(b)
+			{
+				Some(a == b)
+			}
+			else
+			{
+				None
+			}
+		},
+		_ => None,
+	}
+}
+
+pub fn hash_local_file(fullpath: &String, algorithm: HashAlgorithm) -> Option<String>
+{
+	match fs::File::open(fullpath)
+	{
+		Ok(value) => hash_reader(value, algorithm),
+		Err(_) => None,
+	}
+}
+
+fn hash_reader<R: Read>(mut reader: R, algorithm: HashAlgorithm) -> Option<String>
+{
+	let mut md5 = Md5::new();
+	let mut crc32 = crc32fast::Hasher::new();
+	let mut buffer = vec![0u8; 64 * 1024];
+
+	loop
+	{
+		let read = match reader.read(&mut buffer)
+		{
+			Ok(0) => break,
+			Ok(value) => value,
+			Err(_) => return None,
+		};
+
+		match algorithm
+		{
+			HashAlgorithm::Md5 => md5.update(&buffer[..read]),
+			HashAlgorithm::Crc32 => crc32.update(&buffer[..read]),
+		}
+	}
+
+	let hex = match algorithm
+	{
+		HashAlgorithm::Md5 =>
+		{
+			let mut hex = String::with_capacity(32);
+			for byte in md5.finalize()
+			{
+				hex.push_str(&format!("{byte:02x}"));
+			}
+			hex
+		},
+		HashAlgorithm::Crc32 => format!("{:08x}", crc32.finalize()),
+	};
+
+	Some(algorithm.tag(&hex))
+}
+
+//Hashing big files takes a while, so the hashes of local files are remembered along with the size and date they had.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedHash
+{
+	size: u64,
+	date_modified: i64,
+	hash: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LocalHashCache
+{
+	files: HashMap<String, CachedHash>, //Keyed by full path
+}
+
+impl LocalHashCache
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			files: HashMap::new(),
+		}
+	}
+
+	pub fn get_hash(&mut self, fullpath: &String, size: u64, date_modified: i64, algorithm: HashAlgorithm) -> Option<String>
+	{
+		if let Some(cached) = self.files.get(fullpath)
+		{
+			if cached.size == size && cached.date_modified == date_modified && cached.hash.starts_with(&algorithm.tag(""))
+			{
+				return Some(cached.hash.clone());
+			}
+		}
+
+		let hash = hash_local_file(fullpath, algorithm)?;
+		self.files.insert
+		(
+			fullpath.clone(),
+			CachedHash
+			{
+				size,
+				date_modified,
+				hash: hash.clone(),
+			}
+		);
+		Some(hash)
+	}
+}
+
+fn get_cache_folder() -> String
+{
+	format!("{}/cache", get_program_folder())
+}
+
+fn get_cache_location(sync_location: &SyncLocation) -> String
+{
+	format!("{}/{}-hashes.json", get_cache_folder(), sync_location.name_encoded)
+}
+
+pub fn load_hash_cache(sync_location: &SyncLocation) -> LocalHashCache
+{
+	match fs::read_to_string(get_cache_location(sync_location))
+	{
+		Ok(value) =>
+		{
+			match serde_json::from_str(&value)
+			{
+				Ok(value) => value,
+				Err(_) => LocalHashCache::new(), //It's only a cache, everything will be hashed again
+			}
+		},
+		Err(_) => LocalHashCache::new(),
+	}
+}
+
+pub fn save_hash_cache(sync_location: &SyncLocation, hash_cache: &LocalHashCache) -> bool
+{
+	match fs::create_dir_all(get_cache_folder())
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to create cache folder! ({error})");
+			return false;
+		}
+	}
+
+	let json_string = match serde_json::to_string(hash_cache)
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to serialize hash cache to json! ({error})");
+			return false;
+		}
+	};
+
+	match fs::write(get_cache_location(sync_location), json_string)
+	{
+		Ok(_) => true,
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to write hash cache! ({error})");
+			false
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn hash(data: &str, algorithm: HashAlgorithm) -> Option<String>
+	{
+		hash_reader(data.as_bytes(), algorithm)
+	}
+
+	//Test suite of RFC 1321
+	#[test]
+	fn md5_test_vectors()
+	{
+		assert_eq!(hash("", HashAlgorithm::Md5), Some(String::from("md5:d41d8cd98f00b204e9800998ecf8427e")));
+		assert_eq!(hash("a", HashAlgorithm::Md5), Some(String::from("md5:0cc175b9c0f1b6a831c399e269772661")));
+		assert_eq!(hash("abc", HashAlgorithm::Md5), Some(String::from("md5:900150983cd24fb0d6963f7d28e17f72")));
+		assert_eq!(hash("message digest", HashAlgorithm::Md5), Some(String::from("md5:f96b697d7cb7938d525a2f31aaf161d0")));
+		assert_eq!(hash("12345678901234567890123456789012345678901234567890123456789012345678901234567890", HashAlgorithm::Md5), Some(String::from("md5:57edf4a22be3c955ac49da2e2107b67a")));
+	}
+
+	#[test]
+	fn crc32_test_vectors()
+	{
+		assert_eq!(hash("", HashAlgorithm::Crc32), Some(String::from("crc32:00000000")));
+		assert_eq!(hash("abc", HashAlgorithm::Crc32), Some(String::from("crc32:352441c2")));
+		assert_eq!(hash("123456789", HashAlgorithm::Crc32), Some(String::from("crc32:cbf43926")));
+	}
+
+	#[test]
+	fn hashes_only_match_with_the_same_algorithm()
+	{
+		let md5 = hash("abc", HashAlgorithm::Md5);
+		let crc32 = hash("abc", HashAlgorithm::Crc32);
+		assert_eq!(compare_hashes(&md5, &md5.clone()), Some(true));
+		assert_eq!(compare_hashes(&md5, &hash("abd", HashAlgorithm::Md5)), Some(false));
+		assert_eq!(compare_hashes(&md5, &crc32), None);
+		assert_eq!(compare_hashes(&md5, &None), None);
+	}
+}
@@ -0,0 +1,407 @@
+use std::fs;
+use suppaftp::FtpStream;
+
+use crate::config::SyncLocation;
+
+//Read from the synced root on both sides, it's synced like any other file
+pub const IGNORE_FILE_NAME: &str = ".syncignore";
+
+//A single gitignore-style pattern.
+struct IgnoreRule
+{
+	segments: Vec<Vec<char>>, //The pattern split by /, "**" matches any amount of directories
+	negated: bool, //Started with !, brings back what the rules before it ignored
+	directory_only: bool, //Ended with /
+}
+
+//Rules are checked in order and the last one that matches decides, like in .gitignore.
+pub struct IgnoreRules
+{
+	rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			rules: Vec::new(),
+		}
+	}
+
+	//Takes the contents of a .syncignore, one pattern per line
+	pub fn add_patterns(&mut self, patterns: &str)
+	{
+		for line in patterns.lines()
+		{
+			self.add_pattern(line);
+		}
+	}
+
+	pub fn add_pattern(&mut self, pattern: &str)
+	{
+		let pattern = pattern.trim_end();
+		if pattern.is_empty() || pattern.starts_with('#')
+		{
+			return;
+		}
+
+		let (pattern, negated) = match pattern.strip_prefix('!')
+		{
+			Some(value) => (value, true),
+			None => (pattern, false),
+		};
+
+		let (pattern, directory_only) = match pattern.strip_suffix('/')
+		{
+			Some(value) => (value, true),
+			None => (pattern, false),
+		};
+
+		if pattern.is_empty()
+		{
+			return;
+		}
+
+		//Patterns with a / only match from the root, the rest match a name at any depth
+		let anchored = pattern.contains('/');
+
+		let mut segments: Vec<Vec<char>> = Vec::new();
+		if !anchored
+		{
+			segments.push(vec!['*', '*']);
+		}
+		for segment in pattern.split('/')
+		{
+			if !segment.is_empty()
+			{
+				segments.push(segment.chars().collect());
+			}
+		}
+
+		if segments.is_empty()
+		{
+			return;
+		}
+
+		self.rules.push
+		(
+			IgnoreRule
+			{
+				segments,
+				negated,
+				directory_only,
+			}
+		);
+	}
+
+	//relative_path is like the ones in sync.rs, starting with / from the synced root
+	pub fn is_ignored(&self, relative_path: &str, is_directory: bool) -> bool
+	{
+		let path: Vec<Vec<char>> = relative_path
+			.split('/')
+			.filter(|segment| !segment.is_empty())
+			.map(|segment| segment.chars().collect())
+			.collect();
+
+		let mut ignored = false;
+		for rule in &self.rules
+		{
+			if rule.directory_only && !is_directory
+			{
+				continue;
+			}
+
+			if match_segments(&rule.segments, &path)
+			{
+				ignored = !rule.negated;
+			}
+		}
+		ignored
+	}
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool
+{
+	let (first, rest) = match pattern.split_first()
+	{
+		Some(value) => value,
+		None => return path.is_empty(),
+	};
+
+	if first.as_slice() == ['*', '*']
+	{
+		//A trailing ** is everything inside, but not the directory itself
+		if rest.is_empty()
+		{
+			return !path.is_empty();
+		}
+
+		for skip in 0..=path.len()
+		{
+			if match_segments(rest, &path[skip..])
+			{
+				return true;
+			}
+		}
+		return false;
+	}
+
+	match path.split_first()
+	{
+		Some((name, path_rest)) => match_wildcards(first, name) && match_segments(rest, path_rest),
+		None => false,
+	}
+}
+
+//Matches a single name against *, ? and [...], \ makes the next character literal.
+fn match_wildcards(pattern: &[char], name: &[char]) -> bool
+{
+	let first = match pattern.first()
+	{
+		Some(value) => *value,
+		None => return name.is_empty(),
+	};
+
+	match first
+	{
+		'*' =>
+		{
+			for skip in 0..=name.len()
+			{
+				if match_wildcards(&pattern[1..], &name[skip..])
+				{
+					return true;
+				}
+			}
+			false
+		},
+		'?' => !name.is_empty() && match_wildcards(&pattern[1..], &name[1..]),
+		'[' =>
+		{
+			match match_character_class(&pattern[1..], name.first())
+			{
+				Some((matched, length)) => matched && match_wildcards(&pattern[1 + length..], &name[1..]),
+				None => name.first() == Some(&'[') && match_wildcards(&pattern[1..], &name[1..]), //Never closed, so it's just a [
+			}
+		},
+		'\\' if pattern.len() > 1 => name.first() == Some(&pattern[1]) && match_wildcards(&pattern[2..], &name[1..]),
+		_ => name.first() == Some(&first) && match_wildcards(&pattern[1..], &name[1..]),
+	}
+}
+
+//Receives what comes after the [, returns if the character matched and how much of the pattern the class used, or None if it's never closed.
+fn match_character_class(class: &[char], character: Option<&char>) -> Option<(bool, usize)>
+{
+	let mut i = 0;
+	let negated = match class.first()
+	{
+		Some('!') | Some('^') =>
+		{
+			i += 1;
+			true
+		},
+		_ => false,
+	};
+
+	let mut matched = false;
+	let mut first = true;
+	loop
+	{
+		let current = match class.get(i)
+		{
+			Some(value) => *value,
+			None => return None,
+		};
+
+		//A ] right after the [ is part of the class
+		if current == ']' && !first
+		{
+			break;
+		}
+		first = false;
+
+		match (class.get(i + 1), class.get(i + 2))
+		{
+			(Some('-'), Some(end)) if *end != ']' =>
+			{
+				if let Some(character) = character
+				{
+					matched |= current <= *character && *character <= *end;
+				}
+				i += 3;
+			},
+			_ =>
+			{
+				matched |= character == Some(&current);
+				i += 1;
+			}
+		}
+	}
+
+	Some((character.is_some() && matched != negated, i + 1))
+}
+
+//Patterns from the config go first so .syncignore can refine them, and include patterns go last so they always win.
+pub fn load_ignore_rules(sync_location: &SyncLocation, ftp_stream: &mut FtpStream) -> IgnoreRules
+{
+	let mut ignore_rules = IgnoreRules::new();
+
+	for pattern in &sync_location.exclude
+	{
+		ignore_rules.add_pattern(pattern);
+	}
+
+	//It's optional
+	if let Ok(value) = fs::read_to_string(format!("{}/{}", sync_location.local_path, IGNORE_FILE_NAME))
+	{
+		ignore_rules.add_patterns(&value);
+	}
+
+	//Asking with SIZE first, a RETR that fails can leave its answer unread and mess up the next commands
+	let remote_ignore_file = format!("{}/{}", sync_location.remote_path, IGNORE_FILE_NAME);
+	if ftp_stream.size(&remote_ignore_file).is_ok()
+	{
+		if let Ok(value) = ftp_stream.retr_as_buffer(&remote_ignore_file)
+		{
+			ignore_rules.add_patterns(&String::from_utf8_lossy(&value.into_inner()));
+		}
+	}
+
+	for pattern in &sync_location.include
+	{
+		ignore_rules.add_pattern(&format!("!{pattern}"));
+	}
+
+	ignore_rules
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn rules(patterns: &str) -> IgnoreRules
+	{
+		let mut ignore_rules = IgnoreRules::new();
+		ignore_rules.add_patterns(patterns);
+		ignore_rules
+	}
+
+	#[test]
+	fn names_without_a_slash_match_at_any_depth()
+	{
+		let ignore_rules = rules("*.log");
+		assert!(ignore_rules.is_ignored("/a.log", false));
+		assert!(ignore_rules.is_ignored("/x/y/a.log", false));
+		assert!(!ignore_rules.is_ignored("/a.txt", false));
+	}
+
+	#[test]
+	fn patterns_with_a_slash_are_anchored_to_the_root()
+	{
+		let ignore_rules = rules("/build");
+		assert!(ignore_rules.is_ignored("/build", true));
+		assert!(!ignore_rules.is_ignored("/src/build", true));
+
+		let ignore_rules = rules("docs/*.md");
+		assert!(ignore_rules.is_ignored("/docs/a.md", false));
+		assert!(!ignore_rules.is_ignored("/x/docs/a.md", false));
+		assert!(!ignore_rules.is_ignored("/docs/sub/a.md", false));
+	}
+
+	#[test]
+	fn double_star()
+	{
+		let ignore_rules = rules("**/cache");
+		assert!(ignore_rules.is_ignored("/cache", true));
+		assert!(ignore_rules.is_ignored("/a/b/cache", true));
+
+		//Everything inside, but not the directory itself
+		let ignore_rules = rules("logs/**");
+		assert!(ignore_rules.is_ignored("/logs/a", false));
+		assert!(ignore_rules.is_ignored("/logs/a/b", false));
+		assert!(!ignore_rules.is_ignored("/logs", true));
+	}
+
+	#[test]
+	fn double_star_in_the_middle()
+	{
+		let ignore_rules = rules("a/**/b");
+		assert!(ignore_rules.is_ignored("/a/b", false));
+		assert!(ignore_rules.is_ignored("/a/x/b", false));
+		assert!(ignore_rules.is_ignored("/a/x/y/b", false));
+		assert!(!ignore_rules.is_ignored("/a/x/c", false));
+		assert!(!ignore_rules.is_ignored("/x/a/b", false));
+	}
+
+	#[test]
+	fn trailing_slash_only_matches_directories()
+	{
+		let ignore_rules = rules("build/");
+		assert!(ignore_rules.is_ignored("/build", true));
+		assert!(ignore_rules.is_ignored("/src/build", true));
+		assert!(!ignore_rules.is_ignored("/build", false));
+	}
+
+	#[test]
+	fn negation_brings_files_back()
+	{
+		let ignore_rules = rules("*.log\n!keep.log");
+		assert!(ignore_rules.is_ignored("/other.log", false));
+		assert!(!ignore_rules.is_ignored("/keep.log", false));
+		assert!(!ignore_rules.is_ignored("/x/keep.log", false));
+	}
+
+	#[test]
+	fn last_matching_rule_wins()
+	{
+		let ignore_rules = rules("!keep.log\n*.log");
+		assert!(ignore_rules.is_ignored("/keep.log", false));
+
+		let ignore_rules = rules("*.log\n!*.log\n*.log");
+		assert!(ignore_rules.is_ignored("/a.log", false));
+	}
+
+	#[test]
+	fn character_classes_and_wildcards()
+	{
+		let ignore_rules = rules("file[0-9].txt");
+		assert!(ignore_rules.is_ignored("/file3.txt", false));
+		assert!(!ignore_rules.is_ignored("/filea.txt", false));
+
+		let ignore_rules = rules("[!a]*.txt");
+		assert!(ignore_rules.is_ignored("/b.txt", false));
+		assert!(!ignore_rules.is_ignored("/a.txt", false));
+
+		let ignore_rules = rules("[]x]y");
+		assert!(ignore_rules.is_ignored("/]y", false));
+		assert!(ignore_rules.is_ignored("/xy", false));
+
+		let ignore_rules = rules("a?c");
+		assert!(ignore_rules.is_ignored("/abc", false));
+		assert!(!ignore_rules.is_ignored("/ac", false));
+	}
+
+	#[test]
+	fn unclosed_classes_and_escapes_are_literal()
+	{
+		let ignore_rules = rules("[abc");
+		assert!(ignore_rules.is_ignored("/[abc", false));
+		assert!(!ignore_rules.is_ignored("/a", false));
+
+		let ignore_rules = rules("\\*.txt");
+		assert!(ignore_rules.is_ignored("/*.txt", false));
+		assert!(!ignore_rules.is_ignored("/a.txt", false));
+	}
+
+	#[test]
+	fn comments_and_blank_lines_are_skipped()
+	{
+		let ignore_rules = rules("# *.txt\n\n*.tmp\n");
+		assert!(ignore_rules.is_ignored("/a.tmp", false));
+		assert!(!ignore_rules.is_ignored("/a.txt", false));
+		assert!(!ignore_rules.is_ignored("/# *.txt", false));
+	}
+}
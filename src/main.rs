@@ -2,9 +2,18 @@ mod sync;
 mod config;
 mod tui;
 mod args;
+mod state;
+mod ftp;
+mod hash;
+mod ignore;
+mod bandwidth;
+mod case;
+mod normalization;
+mod progress;
 
 use crate::tui::{NewRemoteDetails, TuiResult};
-use crate::config::SyncLocation;
+use crate::config::{SyncLocation, ConflictPolicy, CompareMode, SyncDirection, SymlinkPolicy, TimezoneOffset, NormalizationForm};
+use crate::sync::SyncResult;
 
 use crossterm::execute;
 use crossterm::{queue, style::{Color, Print, SetForegroundColor, SetAttribute, Attribute}};
@@ -44,12 +53,20 @@ fn main()
 					}
 				};
 
-				sync::start_sync_blocking(selected_sync_location, &args);
+				let sync_result = sync::start_sync_blocking(selected_sync_location, &args);
 
 				if args.wait_to_exit
 				{
 					wait_to_exit();
 				}
+
+				//Lets scripts know if a dry run found something to do
+				match sync_result
+				{
+					SyncResult::Failed => std::process::exit(1),
+					SyncResult::ChangesPending => std::process::exit(2),
+					SyncResult::Finished | SyncResult::NothingToChange => (),
+				}
 				break;
 			},
 			TuiResult::CreateRemote(new_remote_details) =>
@@ -133,7 +150,7 @@ fn create_new_remote(new_remote_details: NewRemoteDetails, sync_locations: &Vec<
 				"windows" =>
 				{
 					//Check for the letter drive
-					if !value.starts_with(&['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z'])
+					if !value.starts_with(['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z'])
 					{
 						print_error_to_save_remote(&name);
 						println!("[ERROR] Local path should be absolute (should include drive letter)");
@@ -178,23 +195,37 @@ fn create_new_remote(new_remote_details: NewRemoteDetails, sync_locations: &Vec<
 		Some(value) => value,
 		None => String::from("anon@localhost"),
 	};
-	let advanced_backups = match new_remote_details.advanced_backups
+	let advanced_backups = new_remote_details.advanced_backups.unwrap_or(true);
+	let direction = match new_remote_details.direction
 	{
 		Some(value) => value,
-		None => true,
+		None => SyncDirection::Bidirectional,
 	};
 
 	//Create the remote
 	let sync_location = SyncLocation
 	{
 		name: name.clone(),
-		name_encoded: name_encoded,
-		remote: remote,
-		remote_path: remote_path,
-		local_path: local_path,
-		remote_username: remote_username,
-		remote_password: remote_password,
-		advanced_backups: advanced_backups,
+		name_encoded,
+		remote,
+		remote_path,
+		local_path,
+		remote_username,
+		remote_password,
+		advanced_backups,
+		conflict_policy: ConflictPolicy::NewestWins,
+		compare_mode: CompareMode::Mtime,
+		direction,
+		exclude: Vec::new(),
+		include: Vec::new(),
+		symlinks: SymlinkPolicy::Skip,
+		connections: 1,
+		timezone_offset: TimezoneOffset::Auto,
+		upload_limit: None,
+		download_limit: None,
+		retries: 3,
+		case_insensitive: case::CASE_INSENSITIVE_BY_DEFAULT,
+		unicode_normalization: NormalizationForm::Nfc,
 	};
 
 	//Chech if there isn't another remote with the name name or codified name
@@ -261,8 +292,7 @@ fn encodify_name(name: &String) -> String
 	let _ = encode_unquoted_attribute_to_string(name, &mut result);
 	let result = result.replace("&#x20;", "_");
 	let result = result.replace("&#32;", "_");
-	let result = result.replace("&#95;", "_");
-	result
+	result.replace("&#95;", "_")
 }
 
 fn wait_to_exit()
@@ -272,20 +302,12 @@ fn wait_to_exit()
 
 	loop
 	{
-		match crossterm::event::read()
+		if let Ok(event) = crossterm::event::read()
 		{
-			Ok(event) =>
+			if event.as_key_event().is_some()
 			{
-				match event.as_key_event()
-				{
-					Some(_) =>
-					{
-						break;
-					}
-					None => ()
-				}
-			},
-			Err(_) => ()
+				break;
+			}
 		}
 	}
 }
@@ -0,0 +1,63 @@
+use std::borrow::Cow;
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick, is_nfd_quick};
+
+use crate::config::NormalizationForm;
+
+//Borrowed when the name is already in that form, which is almost always
+pub fn normalize(path: &str, form: NormalizationForm) -> Cow<'_, str>
+{
+	match form
+	{
+		NormalizationForm::Nfc =>
+		{
+			match is_nfc_quick(path.chars())
+			{
+				IsNormalized::Yes => Cow::Borrowed(path),
+				_ => Cow::Owned(path.nfc().collect()),
+			}
+		},
+		NormalizationForm::Nfd =>
+		{
+			match is_nfd_quick(path.chars())
+			{
+				IsNormalized::Yes => Cow::Borrowed(path),
+				_ => Cow::Owned(path.nfd().collect()),
+			}
+		},
+		NormalizationForm::None => Cow::Borrowed(path),
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const NFC: &str = "/caf\u{e9}/r\u{e9}sum\u{e9}.txt";
+	const NFD: &str = "/cafe\u{301}/re\u{301}sume\u{301}.txt";
+
+	#[test]
+	fn both_spellings_end_up_the_same()
+	{
+		assert_eq!(normalize(NFD, NormalizationForm::Nfc), NFC);
+		assert_eq!(normalize(NFC, NormalizationForm::Nfc), NFC);
+		assert_eq!(normalize(NFC, NormalizationForm::Nfd), NFD);
+		assert_eq!(normalize(NFD, NormalizationForm::Nfd), NFD);
+	}
+
+	#[test]
+	fn names_already_normalized_are_borrowed()
+	{
+		assert!(matches!(normalize(NFC, NormalizationForm::Nfc), Cow::Borrowed(_)));
+		assert!(matches!(normalize(NFD, NormalizationForm::Nfd), Cow::Borrowed(_)));
+		assert!(matches!(normalize("/plain/ascii.txt", NormalizationForm::Nfd), Cow::Borrowed(_)));
+		assert!(matches!(normalize(NFD, NormalizationForm::Nfc), Cow::Owned(_)));
+	}
+
+	#[test]
+	fn none_leaves_names_alone()
+	{
+		assert_eq!(normalize(NFD, NormalizationForm::None), NFD);
+		assert_eq!(normalize(NFC, NormalizationForm::None), NFC);
+	}
+}
@@ -0,0 +1,629 @@
+use std::{io::{self, IsTerminal, Read, Write}, sync::{Mutex, MutexGuard}, time::{Duration, Instant}};
+use crossterm::{queue, cursor::{MoveToColumn, MoveUp}, terminal::{self, Clear, ClearType}};
+
+use crate::bandwidth::RateLimiter;
+
+//Redrawing on every chunk would spend more time drawing than transferring
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 20;
+
+//Progress of the transfers of a sync, drawn as a bar for each file being transferred and one for the whole sync.
+//The bars are drawn below the last line printed, so while they're shown everything printed has to go through here (see SyncProgress::output).
+//When stdout isn't a terminal nothing is drawn, and what's printed goes through as it is.
+pub struct SyncProgress
+{
+	interactive: bool,
+	total_files: usize,
+	total_bytes: u64,
+	started: Instant,
+	display: Mutex<Display>,
+}
+
+struct Display
+{
+	files: Vec<FileState>,
+	next_id: usize,
+	finished_files: usize,
+	finished_bytes: u64, //Whole size of the files done with, transferred or not
+	moved_bytes: u64, //What actually went through the connections, to get the speed
+	column: Option<usize>, //Where the last line printed ends, None until a whole line was printed through here
+	drawn_lines: usize,
+	last_drawn: Instant,
+}
+
+struct FileState
+{
+	id: usize,
+	name: String,
+	size: u64,
+	position: u64,
+	moved_bytes: u64,
+	started: Instant,
+	limit: Option<u64>, //Shared with every other transfer going the same way
+}
+
+impl SyncProgress
+{
+	pub fn new(total_files: usize, total_bytes: u64) -> Self
+	{
+		Self
+		{
+			interactive: io::stdout().is_terminal(),
+			total_files,
+			total_bytes,
+			started: Instant::now(),
+			display: Mutex::new(Display
+			{
+				files: Vec::new(),
+				next_id: 0,
+				finished_files: 0,
+				finished_bytes: 0,
+				moved_bytes: 0,
+				column: None,
+				drawn_lines: 0,
+				last_drawn: Instant::now(),
+			}),
+		}
+	}
+
+	//The bar of the file is removed when the returned value is dropped, each attempt at transferring the file gets its own
+	pub fn start_file(&self, name: &str, size: u64, limiter: Option<&RateLimiter>) -> FileProgress<'_>
+	{
+		let mut display = self.lock();
+		let id = display.next_id;
+		display.next_id += 1;
+		display.files.push(FileState
+		{
+			id,
+			name: name.to_string(),
+			size,
+			position: 0,
+			moved_bytes: 0,
+			started: Instant::now(),
+			limit: limiter.map(RateLimiter::get_bytes_per_second),
+		});
+
+		FileProgress
+		{
+			progress: self,
+			id,
+		}
+	}
+
+	//Called once per file when it's done with, whether it was transferred or failed for good
+	pub fn finish_file(&self, size: u64)
+	{
+		let mut display = self.lock();
+		display.finished_files += 1;
+		display.finished_bytes += size;
+	}
+
+	pub fn output(&self) -> ProgressOutput<'_>
+	{
+		ProgressOutput
+		{
+			progress: self,
+			buffer: Vec::new(),
+		}
+	}
+
+	pub fn print(&self, bytes: &[u8])
+	{
+		let mut display = self.lock();
+		let mut stdout = io::stdout().lock();
+		if !self.interactive
+		{
+			let _ = stdout.write_all(bytes);
+			let _ = stdout.flush();
+			return;
+		}
+
+		self.clear(&mut display, &mut stdout);
+		let _ = stdout.write_all(bytes);
+
+		let text = String::from_utf8_lossy(bytes);
+		display.column = match text.rfind('\n')
+		{
+			Some(index) => Some(get_visible_width(&text[index + 1..])),
+			None => display.column.map(|column| column + get_visible_width(&text)),
+		};
+
+		self.draw(&mut display, &mut stdout);
+		let _ = stdout.flush();
+	}
+
+	//Removes the bars, anything printed afterwards without going through here continues right after the last line like before
+	pub fn finish(&self)
+	{
+		let mut display = self.lock();
+		if !self.interactive
+		{
+			return;
+		}
+
+		let mut stdout = io::stdout().lock();
+		self.clear(&mut display, &mut stdout);
+		display.column = None;
+		let _ = stdout.flush();
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Display>
+	{
+		match self.display.lock()
+		{
+			Ok(value) => value,
+			Err(poisoned) => poisoned.into_inner(),
+		}
+	}
+
+	fn update(&self, id: usize, update: impl FnOnce(&mut FileState, &mut u64))
+	{
+		let mut display = self.lock();
+		let display = &mut *display;
+		match display.files.iter_mut().find(|file| file.id == id)
+		{
+			Some(file) => update(file, &mut display.moved_bytes),
+			None => return,
+		}
+
+		if self.interactive && display.last_drawn.elapsed() >= REDRAW_INTERVAL
+		{
+			let mut stdout = io::stdout().lock();
+			self.clear(display, &mut stdout);
+			self.draw(display, &mut stdout);
+			let _ = stdout.flush();
+		}
+	}
+
+	fn remove(&self, id: usize)
+	{
+		let mut display = self.lock();
+		display.files.retain(|file| file.id != id);
+
+		if self.interactive
+		{
+			let mut stdout = io::stdout().lock();
+			self.clear(&mut display, &mut stdout);
+			self.draw(&mut display, &mut stdout);
+			let _ = stdout.flush();
+		}
+	}
+
+	//Goes back to the end of the last line printed
+	fn clear<W: Write>(&self, display: &mut Display, stdout: &mut W)
+	{
+		if display.drawn_lines == 0
+		{
+			return;
+		}
+
+		let _ = queue!(stdout, MoveToColumn(0));
+		if display.drawn_lines > 1
+		{
+			let _ = queue!(stdout, MoveUp(display.drawn_lines as u16 - 1));
+		}
+		let _ = queue!(stdout, Clear(ClearType::FromCursorDown));
+		let _ = queue!(stdout, MoveUp(1));
+		let _ = queue!(stdout, MoveToColumn((display.column.unwrap_or(0) % get_terminal_width()) as u16));
+		display.drawn_lines = 0;
+	}
+
+	fn draw<W: Write>(&self, display: &mut Display, stdout: &mut W)
+	{
+		display.last_drawn = Instant::now();
+		let width = get_terminal_width();
+		let column = match display.column
+		{
+			Some(value) => value,
+			None => return,
+		};
+
+		//A line that fills the terminal exactly leaves the cursor waiting to wrap, there's no safe way to come back to it
+		if column > 0 && column % width == 0
+		{
+			return;
+		}
+
+		let mut lines: Vec<String> = Vec::new();
+		for file in &display.files
+		{
+			lines.push(get_file_line(file, width));
+		}
+		lines.push(self.get_total_line(display, width));
+
+		//Lines that scrolled out of the screen can't be cleared
+		let height = match terminal::size()
+		{
+			Ok((_, rows)) => rows as usize,
+			Err(_) => 24,
+		};
+		if lines.len() >= height
+		{
+			lines.drain(..lines.len() + 1 - height);
+		}
+
+		for line in &lines
+		{
+			let _ = stdout.write_all(b"\n");
+			let _ = stdout.write_all(line.as_bytes());
+		}
+		display.drawn_lines = lines.len();
+	}
+
+	fn get_total_line(&self, display: &Display, width: usize) -> String
+	{
+		let mut done_bytes = display.finished_bytes;
+		for file in &display.files
+		{
+			done_bytes += file.position;
+		}
+		let done_bytes = done_bytes.min(self.total_bytes);
+
+		let speed = get_speed(display.moved_bytes, self.started.elapsed());
+		let details = format!("{}/{}, {}/{} files, {}, {}", format_size(done_bytes), format_size(self.total_bytes), display.finished_files, self.total_files, format_speed(speed, None), format_time_left(self.total_bytes - done_bytes, speed));
+		fit_line("Total", done_bytes, self.total_bytes, &details, width)
+	}
+}
+
+fn get_file_line(file: &FileState, width: usize) -> String
+{
+	let speed = get_speed(file.moved_bytes, file.started.elapsed());
+	let details = format!("{}/{}, {}, {}", format_size(file.position), format_size(file.size), format_speed(speed, file.limit), format_time_left(file.size.saturating_sub(file.position), speed));
+	fit_line(&file.name, file.position, file.size, &details, width)
+}
+
+//Lines never wrap, otherwise they couldn't be cleared. The name is cut from the start, the end of a path tells more.
+fn fit_line(name: &str, done: u64, total: u64, details: &str, width: usize) -> String
+{
+	let bar = get_bar(done, total);
+	let fixed_width = bar.chars().count() + details.chars().count() + 3;
+	let available = width.saturating_sub(1).saturating_sub(fixed_width);
+
+	let name_width = name.chars().count();
+	let name = if name_width <= available
+	{
+		name.to_string()
+	}
+	else if available > 3
+	{
+		format!("...{}", name.chars().skip(name_width + 3 - available).collect::<String>())
+	}
+	else
+	{
+		String::new()
+	};
+
+	let line = format!("{name} {bar} {details}");
+	line.chars().take(width.saturating_sub(1)).collect()
+}
+
+fn get_bar(done: u64, total: u64) -> String
+{
+	let filled = if total == 0
+	{
+		BAR_WIDTH
+	}
+	else
+	{
+		((done as f64 / total as f64) * BAR_WIDTH as f64) as usize
+	}.min(BAR_WIDTH);
+
+	format!("[{}{}]", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled))
+}
+
+fn get_speed(bytes: u64, elapsed: Duration) -> Option<u64>
+{
+	//Too early to tell
+	if elapsed < Duration::from_millis(500) || bytes == 0
+	{
+		return None;
+	}
+
+	Some((bytes as f64 / elapsed.as_secs_f64()) as u64)
+}
+
+fn format_speed(speed: Option<u64>, limit: Option<u64>) -> String
+{
+	let speed = match speed
+	{
+		Some(value) => format!("{}/s", format_size(value)),
+		None => String::from("--/s"),
+	};
+
+	match limit
+	{
+		Some(value) => format!("{speed} (limit {}/s)", format_size(value)),
+		None => speed,
+	}
+}
+
+fn format_time_left(bytes_left: u64, speed: Option<u64>) -> String
+{
+	let seconds = match speed
+	{
+		Some(value) if value > 0 => bytes_left / value,
+		_ => return String::from("-- left"),
+	};
+
+	if seconds >= 3600
+	{
+		format!("{}h {:02}m left", seconds / 3600, seconds % 3600 / 60)
+	}
+	else if seconds >= 60
+	{
+		format!("{}m {:02}s left", seconds / 60, seconds % 60)
+	}
+	else
+	{
+		format!("{seconds}s left")
+	}
+}
+
+pub fn format_size(bytes: u64) -> String
+{
+	let bytes = bytes as f64;
+	if bytes >= 1024.0 * 1024.0 * 1024.0
+	{
+		format!("{:.1} GB", bytes / (1024.0 * 1024.0 * 1024.0))
+	}
+	else if bytes >= 1024.0 * 1024.0
+	{
+		format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+	}
+	else if bytes >= 1024.0
+	{
+		format!("{:.1} KB", bytes / 1024.0)
+	}
+	else
+	{
+		format!("{bytes} B")
+	}
+}
+
+fn get_terminal_width() -> usize
+{
+	match terminal::size()
+	{
+		Ok((columns, _)) if columns > 0 => columns as usize,
+		_ => 80,
+	}
+}
+
+//Colors and other escape sequences don't take any space on the screen
+fn get_visible_width(text: &str) -> usize
+{
+	let mut width = 0;
+	let mut escape = false; //Inside a CSI sequence, they end with a letter (or @, ~ and a few more)
+	for character in text.chars()
+	{
+		if escape
+		{
+			if character != '[' && ('@'..='~').contains(&character)
+			{
+				escape = false;
+			}
+			continue;
+		}
+
+		match character
+		{
+			'\u{1b}' => escape = true,
+			'\r' => width = 0,
+			_ => width += 1,
+		}
+	}
+	width
+}
+
+//One attempt at transferring a file.
+pub struct FileProgress<'a>
+{
+	progress: &'a SyncProgress,
+	id: usize,
+}
+
+impl FileProgress<'_>
+{
+	//Where the transfer starts, when it continues an interrupted one
+	pub fn set_position(&self, position: u64)
+	{
+		self.progress.update(self.id, |file, _| file.position = position);
+	}
+
+	pub fn add(&self, bytes: usize)
+	{
+		self.progress.update(self.id, |file, moved_bytes|
+		{
+			file.position += bytes as u64;
+			file.moved_bytes += bytes as u64;
+			*moved_bytes += bytes as u64;
+		});
+	}
+}
+
+impl Drop for FileProgress<'_>
+{
+	fn drop(&mut self)
+	{
+		self.progress.remove(self.id);
+	}
+}
+
+//Wraps what put_file and append_file read from.
+pub struct ProgressReader<'a, R: Read>
+{
+	inner: R,
+	file_progress: &'a FileProgress<'a>,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R>
+{
+	pub fn new(inner: R, file_progress: &'a FileProgress<'a>) -> Self
+	{
+		Self
+		{
+			inner,
+			file_progress,
+		}
+	}
+}
+
+impl<R: Read> Read for ProgressReader<'_, R>
+{
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>
+	{
+		match self.inner.read(buffer)
+		{
+			Ok(read) =>
+			{
+				self.file_progress.add(read);
+				Ok(read)
+			},
+			Err(error) => Err(error),
+		}
+	}
+}
+
+//Wraps what downloads are written to.
+pub struct ProgressWriter<'a, W: Write>
+{
+	inner: W,
+	file_progress: &'a FileProgress<'a>,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W>
+{
+	pub fn new(inner: W, file_progress: &'a FileProgress<'a>) -> Self
+	{
+		Self
+		{
+			inner,
+			file_progress,
+		}
+	}
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W>
+{
+	fn write(&mut self, buffer: &[u8]) -> io::Result<usize>
+	{
+		match self.inner.write(buffer)
+		{
+			Ok(written) =>
+			{
+				self.file_progress.add(written);
+				Ok(written)
+			},
+			Err(error) => Err(error),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()>
+	{
+		self.inner.flush()
+	}
+}
+
+//Stands in for stdout while the bars are shown. What's written is printed on flush, the bars are redrawn once per flush instead of once per write.
+pub struct ProgressOutput<'a>
+{
+	progress: &'a SyncProgress,
+	buffer: Vec<u8>,
+}
+
+impl Write for ProgressOutput<'_>
+{
+	fn write(&mut self, buffer: &[u8]) -> io::Result<usize>
+	{
+		self.buffer.extend_from_slice(buffer);
+		Ok(buffer.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()>
+	{
+		if !self.buffer.is_empty()
+		{
+			self.progress.print(&self.buffer);
+			self.buffer.clear();
+		}
+		Ok(())
+	}
+}
+
+impl Drop for ProgressOutput<'_>
+{
+	fn drop(&mut self)
+	{
+		let _ = self.flush();
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn sizes()
+	{
+		assert_eq!(format_size(0), "0 B");
+		assert_eq!(format_size(1023), "1023 B");
+		assert_eq!(format_size(1024), "1.0 KB");
+		assert_eq!(format_size(1536), "1.5 KB");
+		assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+		assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+	}
+
+	#[test]
+	fn speeds()
+	{
+		assert_eq!(get_speed(1000, Duration::from_millis(100)), None);
+		assert_eq!(get_speed(0, Duration::from_secs(2)), None);
+		assert_eq!(get_speed(2048, Duration::from_secs(2)), Some(1024));
+
+		assert_eq!(format_speed(None, None), "--/s");
+		assert_eq!(format_speed(Some(2048), None), "2.0 KB/s");
+		assert_eq!(format_speed(Some(2048), Some(300 * 1024)), "2.0 KB/s (limit 300.0 KB/s)");
+		assert_eq!(format_speed(None, Some(1024)), "--/s (limit 1.0 KB/s)");
+	}
+
+	#[test]
+	fn time_left()
+	{
+		assert_eq!(format_time_left(100, None), "-- left");
+		assert_eq!(format_time_left(100, Some(0)), "-- left");
+		assert_eq!(format_time_left(100, Some(10)), "10s left");
+		assert_eq!(format_time_left(125, Some(1)), "2m 05s left");
+		assert_eq!(format_time_left(3 * 3600 + 7 * 60, Some(1)), "3h 07m left");
+	}
+
+	#[test]
+	fn bars()
+	{
+		assert_eq!(get_bar(0, 100), format!("[{}]", "-".repeat(BAR_WIDTH)));
+		assert_eq!(get_bar(50, 100), format!("[{}{}]", "#".repeat(BAR_WIDTH / 2), "-".repeat(BAR_WIDTH / 2)));
+		assert_eq!(get_bar(200, 100), format!("[{}]", "#".repeat(BAR_WIDTH)));
+		assert_eq!(get_bar(0, 0), format!("[{}]", "#".repeat(BAR_WIDTH)));
+	}
+
+	#[test]
+	fn lines_fit_the_terminal()
+	{
+		let line = fit_line("/some/long/directory/file.txt", 0, 100, "details", 50);
+		assert!(line.chars().count() < 50, "{line}");
+		assert!(line.starts_with("...") && line.contains("/file.txt "), "{line}");
+
+		//Too narrow for any of the name
+		assert_eq!(fit_line("/a.txt", 0, 100, "details", 30), format!(" [{}] detai", "-".repeat(BAR_WIDTH)));
+
+		assert_eq!(fit_line("/a.txt", 0, 100, "details", 80), format!("/a.txt [{}] details", "-".repeat(BAR_WIDTH)));
+	}
+
+	#[test]
+	fn visible_width()
+	{
+		assert_eq!(get_visible_width("abc"), 3);
+		assert_eq!(get_visible_width("\u{1b}[31mred\u{1b}[0m"), 3);
+		assert_eq!(get_visible_width("before\rafter"), 5);
+	}
+}
@@ -0,0 +1,124 @@
+use std::{fs, collections::HashMap};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::config::{SyncLocation, get_program_folder};
+
+//What both sides of a file looked like right after the last successful sync.
+//This is the common ancestor used to know which side changed since then.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SyncedFileState
+{
+	pub local_date_modified: i64,
+	pub remote_date_modified: i64,
+	#[serde(default)]
+	pub local_size: Option<u64>, //Sizes and hash weren't saved by older versions
+	#[serde(default)]
+	pub remote_size: Option<u64>,
+	#[serde(default)]
+	pub hash: Option<String>, //Hash of the content both sides had, only saved when comparing by checksum
+}
+
+//What the clock probe found the last time it ran, so the server doesn't have to be probed on every sync.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TimezoneProbe
+{
+	pub list_timezone_offset: i64, //Minutes the dates in LIST are ahead of UTC
+	pub local_timezone_offset: i64, //Minutes this computer was ahead of UTC, if it changes the server's timezone likely did too (like when clocks change for summer)
+	pub probed_at: i64, //Unix timestamp
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SyncState
+{
+	pub files: HashMap<String, SyncedFileState>, //Keyed by relative path
+	#[serde(default)]
+	pub timezone_probe: Option<TimezoneProbe>, //Only used when timezone_offset is auto
+}
+
+impl SyncState
+{
+	pub fn new() -> Self
+	{
+		Self
+		{
+			files: HashMap::new(),
+			timezone_probe: None,
+		}
+	}
+}
+
+fn get_state_folder() -> String
+{
+	format!("{}/state", get_program_folder())
+}
+
+fn get_state_location(sync_location: &SyncLocation) -> String
+{
+	format!("{}/{}.json", get_state_folder(), sync_location.name_encoded)
+}
+
+//If there's no state saved (or it can't be read), an empty state is returned, which makes the sync behave like the first one ever made.
+pub fn load_sync_state(sync_location: &SyncLocation) -> SyncState
+{
+	let state_file = match fs::read_to_string(get_state_location(sync_location))
+	{
+		Ok(value) => value,
+		Err(_) => return SyncState::new(),
+	};
+
+	match serde_json::from_str(&state_file)
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			println!("[WARN] Failed to parse the sync state of this remote, it will be treated as if it was never synced before. ({error})");
+			SyncState::new()
+		}
+	}
+}
+
+pub fn save_sync_state(sync_location: &SyncLocation, sync_state: &SyncState) -> bool
+{
+	match fs::create_dir_all(get_state_folder())
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to create sync state folder! ({error})");
+			return false;
+		}
+	}
+
+	let json_string = match serde_json::to_string(sync_state)
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to serialize sync state to json! ({error})");
+			return false;
+		}
+	};
+
+	//Write to a temporary file first, so a crash while saving doesn't leave a broken state behind
+	let state_location = get_state_location(sync_location);
+	let temporary_location = format!("{state_location}.tmp");
+	match fs::write(&temporary_location, json_string)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to write sync state file! ({error})");
+			return false;
+		}
+	}
+
+	match fs::rename(&temporary_location, &state_location)
+	{
+		Ok(_) => true,
+		Err(error) =>
+		{
+			println!("[ERROR] Failed to replace sync state file! ({error})");
+			false
+		}
+	}
+}
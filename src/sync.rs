@@ -1,9 +1,14 @@
-use std::{fs, str::FromStr, io::{self, Write}, time::Duration};
-use chrono::{DateTime, Timelike, Utc};
-use suppaftp::{FtpStream, list};
-use crossterm::{queue, style::{Color, Print, SetForegroundColor, SetAttribute, Attribute}};
+use std::{fs, env, borrow::Cow, str::FromStr, io::{self, Write, Seek, SeekFrom}, time::{Duration, SystemTime}, collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::{Mutex, MutexGuard, atomic::{AtomicBool, AtomicUsize, Ordering}}, thread};
+use chrono::{DateTime, Local, Timelike, Utc};
+use suppaftp::{FtpError, FtpStream, list, types::FileType};
+use crossterm::{queue, style::{Color, Print, SetForegroundColor, SetAttribute, Attribute}, event::{self, KeyCode, KeyEventKind}, terminal};
 
-use crate::{config::{SyncLocation, get_program_folder}, args::{Args, LogLevel}};
+use crate::{config::{SyncLocation, ConflictPolicy, CompareMode, SyncDirection, SymlinkPolicy, TimezoneOffset, NormalizationForm, get_program_folder}, args::{Args, LogLevel}, state::{self, SyncState, SyncedFileState, TimezoneProbe}, ftp::{self, ServerFeatures}, hash::{self, HashAlgorithm, LocalHashCache}, ignore::{self, IgnoreRules}, case, normalization, tui::{self, ReviewItem, ReviewAction}, bandwidth::{self, RateLimiter, LimitedReader, LimitedWriter, TransferLimits}, progress::{SyncProgress, FileProgress, ProgressReader, ProgressWriter}};
+
+const TEMPORARY_FILE_SUFFIX: &str = ".sync-remote-tmp";
+const MAX_CLOCK_SKEW: i64 = 60; //Seconds, dates only have seconds and LIST ones only minutes
+const MAX_RETRY_DELAY: u64 = 60; //Seconds
+const TIMEZONE_PROBE_MAX_AGE: i64 = 24 * 60 * 60; //Seconds
 
 #[derive(Clone, Debug)]
 struct File
@@ -12,6 +17,11 @@ struct File
 	fullpath: String,
 	relative_path: String,
 	date_modified: DateTime<Utc>,
+	date_is_exact: bool, //False if the date came from LIST, which isn't precise to the second
+	size: u64,
+	hash: Option<String>, //Only calculated when comparing by checksum, see HashAlgorithm::tag
+	is_directory: bool, //Directories are synced too, so empty ones exist on both sides
+	symlink_target: Option<String>, //Only for links synced as links (SymlinkPolicy::Preserve), empty if the server didn't say where it points to
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -19,26 +29,48 @@ enum SyncVeredict
 {
 	UploadToRemote,
 	DownloadToLocal,
+	DeleteLocal,
+	DeleteRemote,
+	KeepBoth,
+	Conflict, //Changed on both sides, stays like this if the conflict is skipped
+	Skip, //Set by the user when reviewing the plan (or if it's a file on one side and a directory on the other), it's left as it is and isn't remembered as synced
 	DoNothing,
 	NotDecidedYet,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ConflictResolution
+{
+	KeepLocal,
+	KeepRemote,
+	KeepBoth,
+	Skip,
+	CaseCollision, //Only differs in case from another file, it's left as it is until one of them is renamed
+}
+
 #[derive(Clone, Debug)]
 struct LinkedFile
 {
-	relative_path: String,
+	relative_path: String, //Always written like it is on the remote
 	relative_directory: String,
+	local_relative_path: Option<String>, //Only when it goes locally inside a directory that's written differently there, see get_local_location
 	local_file: Option<File>,
 	remote_file: Option<File>,
 	sync_veredict: SyncVeredict,
+	last_synced: Option<SyncedFileState>, //How the file was left by the last sync, None if it was never synced
+	conflict_resolution: Option<ConflictResolution>, //Only set if the file changed on both sides or collides with another one
 }
 
 struct Report
 {
 	uploaded: usize,
 	downloaded: usize,
+	deleted_local: usize,
+	deleted_remote: usize,
 	ignored: usize,
 	errors: usize,
+	conflicts: Vec<(String, ConflictResolution)>,
+	skipped: Vec<(String, String)>, //Relative path and why it was left out
 }
 
 impl Report
@@ -49,11 +81,28 @@ impl Report
 		{
 			uploaded: 0,
 			downloaded: 0,
+			deleted_local: 0,
+			deleted_remote: 0,
 			ignored: 0,
 			errors: 0,
+			conflicts: Vec::new(),
+			skipped: Vec::new(),
 		}
 	}
 
+	//Adds up what another connection synced
+	fn add(&mut self, other: Report)
+	{
+		self.uploaded += other.uploaded;
+		self.downloaded += other.downloaded;
+		self.deleted_local += other.deleted_local;
+		self.deleted_remote += other.deleted_remote;
+		self.ignored += other.ignored;
+		self.errors += other.errors;
+		self.conflicts.extend(other.conflicts);
+		self.skipped.extend(other.skipped);
+	}
+
 	fn print(&self)
 	{
 		let mut stdout = io::stdout();
@@ -67,6 +116,14 @@ impl Report
 		let _ = queue!(stdout, SetForegroundColor(Color::Blue));
 		let _ = queue!(stdout, Print("Downloaded"));
 		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!(", {} ", self.deleted_local)));
+		let _ = queue!(stdout, SetForegroundColor(Color::Magenta));
+		let _ = queue!(stdout, Print("Deleted locally"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!(", {} ", self.deleted_remote)));
+		let _ = queue!(stdout, SetForegroundColor(Color::Magenta));
+		let _ = queue!(stdout, Print("Deleted on remote"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 		let _ = queue!(stdout, Print(format!(", {} ", self.ignored)));
 		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
 		let _ = queue!(stdout, Print("Ignored"));
@@ -77,11 +134,49 @@ impl Report
 		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
 		let _ = queue!(stdout, Print(".\n"));
+
+		if !self.conflicts.is_empty()
+		{
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, Print(format!("\n{} ", self.conflicts.len())));
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print("Conflicts"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(":\n"));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			for (relative_path, conflict_resolution) in &self.conflicts
+			{
+				let _ = queue!(stdout, Print(format!("   {relative_path} ({})\n", get_conflict_resolution_description(*conflict_resolution))));
+			}
+		}
+
+		if !self.skipped.is_empty()
+		{
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, Print(format!("\n{} ", self.skipped.len())));
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("Skipped"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(":\n"));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			for (relative_path, reason) in &self.skipped
+			{
+				let _ = queue!(stdout, Print(format!("   {relative_path} ({reason})\n")));
+			}
+		}
 		let _ = stdout.flush();
 	}
 }
 
-pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
+pub enum SyncResult
+{
+	Finished,
+	Failed, //The sync couldn't even start
+	ChangesPending, //Only for dry runs
+	NothingToChange, //Only for dry runs
+}
+
+pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args) -> SyncResult
 {
 	let mut stdout = io::stdout();
 
@@ -89,7 +184,7 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 		let _ = queue!(stdout, Print("\nRemote: "));
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-		let _ = queue!(stdout, Print(format!("{}", sync_location.name)));
+		let _ = queue!(stdout, Print(&sync_location.name));
 		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 		let _ = queue!(stdout, Print("\nHost: "));
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
@@ -114,7 +209,7 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 			let _ = queue!(stdout, Print(format!("Failed to connect to server! ({})\n", error)));
 			let _ = stdout.flush();
-			return;
+			return SyncResult::Failed;
 		}
 	};
 	//let mut ftp_stream = ftp_stream.active_mode(Duration::from_secs(120));
@@ -141,10 +236,43 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 			let _ = queue!(stdout, Print(format!("Failed to log in! ({})\n", error)));
 			let _ = stdout.flush();
-			return;
+			return SyncResult::Failed;
+		}
+	}
+
+	//ASCII mode would change line endings, files have to arrive exactly as they are
+	match ftp_stream.transfer_type(FileType::Binary)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print("\n[ERROR] "));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to switch to binary transfers! ({})\n", error)));
+			let _ = stdout.flush();
+			return SyncResult::Failed;
 		}
 	}
 
+	let mut server_features = ftp::get_server_features(&mut ftp_stream);
+
+	let sync_state = state::load_sync_state(sync_location);
+
+	let timezone_probe = match sync_location.timezone_offset
+	{
+		TimezoneOffset::Fixed(minutes) =>
+		{
+			server_features.list_timezone_offset = minutes;
+			None
+		},
+		TimezoneOffset::Auto => check_server_clock(sync_location, &mut ftp_stream, &mut server_features, sync_state.timezone_probe, args),
+	};
+
+	let ignore_rules = ignore::load_ignore_rules(sync_location, &mut ftp_stream);
+
 
 	{ //Listing remote files message
 		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
@@ -155,7 +283,8 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
 		let _ = stdout.flush();
 	}
-	let all_remote_files = get_all_remote_files_recursive_from(&sync_location.remote_path, &mut ftp_stream, args);
+	let mut skipped: Vec<(String, String)> = Vec::new();
+	let (all_remote_files, remote_listing_complete) = get_all_remote_files_recursive_from(&sync_location.remote_path, sync_location, &mut ftp_stream, &mut server_features, &ignore_rules, sync_location.symlinks, &mut skipped);
 
 
 	{ //Listing local files message
@@ -167,10 +296,20 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
 		let _ = stdout.flush();
 	}
-	let all_local_files = get_all_local_files_recursive_from(&sync_location.local_path, args);
+	let (all_local_files, local_listing_complete) = get_all_local_files_recursive_from(&sync_location.local_path, &ignore_rules, sync_location.symlinks, &mut skipped, args);
 
 
-	if sync_location.advanced_backups
+	if args.dry_run
+	{ //not Making a backup message
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Grey));
+		let _ = queue!(stdout, Print("*"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Not making a backup (dry run)...\n"));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = stdout.flush();
+	}
+	else if sync_location.advanced_backups
 	{
 		{ //Making a backup message
 			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
@@ -199,7 +338,7 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
 				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 				let _ = stdout.flush();
-				return;
+				return SyncResult::Failed;
 			}
 		}
 	}
@@ -225,44 +364,213 @@ pub fn start_sync_blocking(sync_location: &SyncLocation, args: &Args)
 		let _ = stdout.flush();
 	}
 
-	let all_files_linked = link_all_files(&all_remote_files, &all_local_files, sync_location);
+	let all_files_linked = link_all_files(all_remote_files, all_local_files, &sync_location.remote_path, &sync_location.local_path, &sync_state, sync_location.case_insensitive, sync_location.unicode_normalization);
+
+	let mut hash_cache = hash::load_hash_cache(sync_location);
+
+	let all_files_linked = if sync_location.compare_mode == CompareMode::Checksum
+	{
+		{ //Comparing contents message
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("*"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(" Comparing contents...\n"));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = stdout.flush();
+		}
+		hash_linked_files(all_files_linked, &mut ftp_stream, &mut server_features, &mut hash_cache)
+	}
+	else
+	{
+		all_files_linked
+	};
+
+	let all_files_linked = set_sync_veredicts(all_files_linked, remote_listing_complete, local_listing_complete, sync_location.compare_mode, sync_location.direction, args);
+
+	if args.dry_run
+	{
+		return match print_plan(&all_files_linked, sync_location)
+		{
+			true => SyncResult::ChangesPending,
+			false => SyncResult::NothingToChange,
+		};
+	}
+
+	let all_files_linked = if args.review
+	{
+		review_plan(all_files_linked)
+	}
+	else
+	{
+		all_files_linked
+	};
+
+	let all_files_linked = resolve_conflicts(all_files_linked, sync_location);
 
-	let all_files_linked = set_sync_veredicts(all_files_linked, args);
+	let transfer_limits = TransferLimits::new(sync_location, args);
 	
 	{ //Syncing message
 		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
 		let _ = queue!(stdout, Print("*"));
 		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-		let _ = queue!(stdout, Print(" Syncing...\n"));
+		let _ = queue!(stdout, Print(" Syncing..."));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		if let Some(limiter) = &transfer_limits.upload
+		{
+			let _ = queue!(stdout, Print(format!(" (uploads limited to {})", bandwidth::format_rate(limiter.get_bytes_per_second()))));
+		}
+		if let Some(limiter) = &transfer_limits.download
+		{
+			let _ = queue!(stdout, Print(format!(" (downloads limited to {})", bandwidth::format_rate(limiter.get_bytes_per_second()))));
+		}
+		let _ = queue!(stdout, Print("\n"));
+		let _ = stdout.flush();
+	}
+
+	let completed = sync_files(all_files_linked, skipped, sync_location, &mut ftp_stream, &mut server_features, &mut hash_cache, timezone_probe, &transfer_limits, args);
+
+	if sync_location.compare_mode == CompareMode::Checksum
+	{
+		//Not a big deal if it fails, files will just be hashed again next time
+		let _ = hash::save_hash_cache(sync_location, &hash_cache);
+	}
+
+	if !completed
+	{
+		return SyncResult::Failed;
+	}
+
+	SyncResult::Finished
+}
+
+//Finds the timezone of LIST dates and warns if the server's clock is wrong, a wrong clock gives wrong dates to what the server writes by itself.
+//The server is only probed again once the last probe is a day old or this computer changed its timezone, what was found is returned to be saved in the state.
+fn check_server_clock(sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, last_probe: Option<TimezoneProbe>, args: &Args) -> Option<TimezoneProbe>
+{
+	let mut stdout = io::stdout();
+
+	let now = Utc::now();
+	let local_timezone_offset = i64::from(Local::now().offset().local_minus_utc() / 60);
+
+	//An old probe is still better than nothing when the server can't be probed again
+	if let Some(last_probe) = last_probe
+	{
+		server_features.list_timezone_offset = last_probe.list_timezone_offset;
+		if now.timestamp() - last_probe.probed_at < TIMEZONE_PROBE_MAX_AGE && last_probe.local_timezone_offset == local_timezone_offset
+		{
+			return Some(last_probe);
+		}
+	}
+
+	//Dry runs don't write anything, not even this
+	if args.dry_run
+	{
+		if !server_features.mlsd && last_probe.is_none()
+		{
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("[WARN] "));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print("The timezone of the server can't be checked in a dry run, its dates are taken as UTC. Set timezone_offset in this remote to avoid this.\n"));
+			let _ = stdout.flush();
+		}
+		return last_probe;
+	}
+
+	let marker_fullpath = format!("{}/.clock-probe-{}{TEMPORARY_FILE_SUFFIX}", sync_location.remote_path, get_host_name());
+	let clock_probe = match ftp::probe_server_clock(ftp_stream, server_features, &marker_fullpath)
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			if args.log_level == LogLevel::Verbose
+			{
+				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+				let _ = queue!(stdout, Print("[WARN] "));
+				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(format!("Failed to check the clock of the server, {error}\n")));
+				let _ = stdout.flush();
+			}
+			return last_probe;
+		}
+	};
+
+	server_features.list_timezone_offset = clock_probe.list_timezone_offset;
+
+	//MLSD dates are in UTC, so the timezone only matters if LIST is used
+	if clock_probe.list_timezone_offset != 0 && (!server_features.mlsd || args.log_level == LogLevel::Verbose)
+	{
+		let offset = TimezoneOffset::Fixed(clock_probe.list_timezone_offset).to_config_value();
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Cyan));
+		let _ = queue!(stdout, Print("[INFO] "));
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!("The server lists dates in UTC{offset}, they will be turned into UTC. Set timezone_offset to \"{offset}\" in this remote to skip this check.\n")));
 		let _ = stdout.flush();
 	}
 
-	sync_files(&all_files_linked, sync_location, &mut ftp_stream, args);
+	match clock_probe.clock_skew
+	{
+		Some(clock_skew) if clock_skew.abs() >= MAX_CLOCK_SKEW =>
+		{
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("[WARN] "));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("The clock of the server is {} seconds {} this computer's, files changed on the server may look newer or older than they are.\n", clock_skew.abs(), if clock_skew > 0 { "ahead of" } else { "behind" })));
+			let _ = stdout.flush();
+		},
+		_ => (),
+	}
+
+	Some
+	(
+		TimezoneProbe
+		{
+			list_timezone_offset: clock_probe.list_timezone_offset,
+			local_timezone_offset,
+			probed_at: now.timestamp(),
+		}
+	)
 }
 
-fn get_all_remote_files_recursive_from(directory: &String, ftp_stream: &mut FtpStream, args: &Args) -> Vec<File>
+//Excluded directories are never listed, so nothing inside them is either.
+//Links are only synced when they're kept as links, the server can't be asked what's on the other side of them.
+fn get_all_remote_files_recursive_from(directory: &str, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, ignore_rules: &IgnoreRules, symlinks: SymlinkPolicy, skipped: &mut Vec<(String, String)>) -> (Vec<File>, bool)
 {
-	let mut current_directory: String = directory.clone();
+	let mut current_directory: String = directory.to_string();
 	let mut tree: Vec<File> = Vec::new();
+	let mut complete = true; //False if any directory couldn't be listed, so missing files can't be trusted as deleted
 	
 	let mut directories: Vec<String> = Vec::new();
 	let mut i: usize = 0;
 	
 	loop
 	{
-		match list_remote_directory(&current_directory, ftp_stream, args)
+		match list_remote_directory(&current_directory, sync_location, ftp_stream, server_features)
 		{
-			Some(files_vector) =>
+			Some(listing) =>
 			{
-				if files_vector.is_empty()
+				if !listing.complete
 				{
-					()
+					complete = false;
 				}
 
-				for ftp_file in files_vector
+				for ftp_file in listing.files
 				{
+					//Leftovers of interrupted uploads aren't part of the synced files
+					if is_temporary_file(ftp_file.name())
+					{
+						continue;
+					}
+
 					let fullpath = if current_directory == "/"
 					{
 						format!("{}{}", current_directory, ftp_file.name())
@@ -271,22 +579,54 @@ fn get_all_remote_files_recursive_from(directory: &String, ftp_stream: &mut FtpS
 					{
 						format!("{}/{}", current_directory, ftp_file.name())
 					};
+					let relative_path = get_relative_path(&fullpath, directory);
+
+					if ignore_rules.is_ignored(&relative_path, ftp_file.is_directory())
+					{
+						continue;
+					}
+
+					let listed_date_modified: DateTime<Utc> = if listing.dates_in_server_timezone
+					{
+						ftp::get_list_date_modified(&ftp_file, server_features)
+					}
+					else
+					{
+						ftp_file.modified().into()
+					};
 
 					if ftp_file.is_directory()
 					{
+						tree.push
+						(
+							File
+							{
+								directory: current_directory.clone(),
+								fullpath: fullpath.clone(),
+								relative_path: relative_path.clone(),
+								date_modified: listed_date_modified,
+								date_is_exact: listing.dates_are_exact,
+								size: 0,
+								hash: None,
+								is_directory: true,
+								symlink_target: None,
+							}
+						);
 						directories.push(fullpath);
 					}
 					else if ftp_file.is_file()
 					{
-						let date_modified: DateTime<Utc> = ftp_file.modified().into();
-						let relative_path = fullpath.clone().replacen(directory, "", 1).to_string();
-						let relative_path = if relative_path.starts_with("/")
+						let (date_modified, date_is_exact) = if listing.dates_are_exact
 						{
-							relative_path
+							(listed_date_modified, true)
 						}
 						else
 						{
-							format!("/{relative_path}")
+							match ftp::get_remote_date_modified_exact(ftp_stream, server_features, &fullpath)
+							{
+								Some(value) => (value, true),
+								None => (listed_date_modified, false),
+							}
 						};
 
 						tree.push
@@ -295,16 +635,61 @@ fn get_all_remote_files_recursive_from(directory: &String, ftp_stream: &mut FtpS
 							{
 								directory: current_directory.clone(),
 								fullpath: fullpath.clone(),
-								relative_path: relative_path,
-								date_modified: date_modified,
+								relative_path,
+								date_modified,
+								date_is_exact,
+								size: ftp_file.size() as u64,
+								hash: None,
+								is_directory: false,
+								symlink_target: None,
 								//handler: FileHandler::FtpFile(Some(ftp_file.clone())),
 							}
 						);
 					}
+					else if ftp_file.is_symlink()
+					{
+						if symlinks != SymlinkPolicy::Preserve
+						{
+							print_skipped_symlink(&relative_path, "symbolic link on the remote");
+							skipped.push((relative_path, String::from("symbolic link on the remote")));
+							continue;
+						}
+
+						//MLSD lines come with an empty target
+						let symlink_target = match ftp_file.symlink()
+						{
+							Some(value) if !value.as_os_str().is_empty() => value.to_string_lossy().to_string(),
+							_ =>
+							{
+								match listing.symlink_targets.get(ftp_file.name())
+								{
+									Some(value) => value.clone(),
+									None => String::new(),
+								}
+							}
+						};
+
+						tree.push
+						(
+							File
+							{
+								directory: current_directory.clone(),
+								fullpath: fullpath.clone(),
+								relative_path,
+								date_modified: listed_date_modified,
+								date_is_exact: listing.dates_are_exact,
+								size: 0,
+								hash: None,
+								is_directory: false,
+								symlink_target: Some(symlink_target),
+							}
+						);
+					}
 				}
 			},
 			None =>
 			{
+				complete = false;
 				let mut stdout = io::stdout();
 				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
@@ -324,68 +709,177 @@ fn get_all_remote_files_recursive_from(directory: &String, ftp_stream: &mut FtpS
 		i += 1;
 	}
 
-	tree
+	(tree, complete)
 }
 
-fn list_remote_directory(directory: &String, ftp_stream: &mut FtpStream, args: &Args) -> Option<Vec<list::File>>
+struct RemoteDirectoryListing
 {
-	let directory_listing = match ftp_stream.list(Some(directory.as_str()))
+	files: Vec<list::File>,
+	dates_are_exact: bool, //MLSD gives dates in UTC to the second, LIST doesn't
+	complete: bool, //False if some entries couldn't be understood and were skipped
+	dates_in_server_timezone: bool, //LIST dates are in the server's timezone, MLSD ones in UTC
+	symlink_targets: HashMap<String, String>, //By name, only for the links MLSD said where they point to, LIST puts it in the file itself
+}
+
+//Lists again on a new connection if it was lost, otherwise a single dropped connection would leave the whole listing incomplete
+fn list_remote_directory(directory: &String, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures) -> Option<RemoteDirectoryListing>
+{
+	let mut attempt: u32 = 0;
+	loop
 	{
-		Ok(value) => value,
-		Err(error) =>
+		let error = match try_list_remote_directory(directory, ftp_stream, server_features)
 		{
-			let mut stdout = io::stdout();
-			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
-			let _ = queue!(stdout, Print("\n[WARN] "));
-			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-			let _ = queue!(stdout, Print(format!("Failed to list content of directory \"{directory}\", ({error})\n")));
-			let _ = stdout.flush();
+			Ok(value) => return Some(value),
+			Err(error) => error,
+		};
+
+		if !ftp::is_transient_error(&error) || attempt >= sync_location.retries
+		{
+			print_failed_to_list_remote_directory(directory, &error.to_string());
 			return None;
 		}
-	};
 
-	let mut directory_contents: Vec<list::File> = Vec::with_capacity(directory_listing.len());
-	
-	for item in directory_listing
+		attempt += 1;
+		let mut stdout = io::stdout();
+		wait_and_reconnect(&format!("Failed to list content of directory \"{directory}\", ({error})"), attempt, sync_location, ftp_stream, server_features, &mut stdout);
+		let _ = queue!(stdout, Print("\n"));
+		let _ = stdout.flush();
+	}
+}
+
+//Uses MLSD when the server supports it, LIST is only a fallback because its format depends on the server, its locale and its timezone.
+fn try_list_remote_directory(directory: &String, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures) -> Result<RemoteDirectoryListing, FtpError>
+{
+	if server_features.mlsd
 	{
-		match list::File::from_str(item.as_str())
+		match ftp_stream.mlsd(Some(directory.as_str()))
 		{
-			Ok(value) =>
+			Ok(directory_listing) =>
 			{
-				directory_contents.push(value);
+				let mut listing = RemoteDirectoryListing
+				{
+					files: Vec::with_capacity(directory_listing.len()),
+					dates_are_exact: true,
+					complete: true,
+					dates_in_server_timezone: false,
+					symlink_targets: HashMap::new(),
+				};
+
+				for item in directory_listing
+				{
+					match ftp::parse_mlsd_line(item.as_str())
+					{
+						Some(Ok(value)) =>
+						{
+							if !ftp::mlsd_line_has_date(item.as_str())
+							{
+								listing.dates_are_exact = false;
+							}
+							if let Some(symlink_target) = ftp::get_mlsd_line_symlink_target(item.as_str())
+							{
+								listing.symlink_targets.insert(value.name().to_string(), symlink_target);
+							}
+							listing.files.push(value);
+						},
+						Some(Err(error)) =>
+						{
+							listing.complete = false;
+							print_unparseable_remote_entry(directory, &item, &error.to_string());
+						},
+						None => (),
+					}
+				}
+
+				return Ok(listing);
 			},
 			Err(error) =>
 			{
-				let mut stdout = io::stdout();
-				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-				let _ = queue!(stdout, SetForegroundColor(Color::Red));
-				let _ = queue!(stdout, Print("\n[ERROR] "));
-				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-				let _ = queue!(stdout, Print(format!("Failed to parse remote directory! ({})\n", error)));
-				let _ = stdout.flush();
-				if !args.continue_on_error
+				if ftp::is_not_implemented(&error)
 				{
-					std::process::exit(1);
+					server_features.mlsd = false;
+				}
+				else
+				{
+					return Err(error);
 				}
-				continue;
 			}
 		}
 	}
 
-	Some(directory_contents)
+	let directory_listing = ftp_stream.list(Some(directory.as_str()))?;
+
+	let mut listing = RemoteDirectoryListing
+	{
+		files: Vec::with_capacity(directory_listing.len()),
+		dates_are_exact: false,
+		complete: true,
+		dates_in_server_timezone: true,
+		symlink_targets: HashMap::new(),
+	};
+	
+	for item in directory_listing
+	{
+		match list::File::from_str(item.as_str())
+		{
+			Ok(value) =>
+			{
+				listing.files.push(value);
+			},
+			Err(error) =>
+			{
+				//Some servers add lines like "total 12", so one bad line isn't a reason to stop
+				listing.complete = false;
+				print_unparseable_remote_entry(directory, &item, &error.to_string());
+			}
+		}
+	}
+
+	Ok(listing)
+}
+
+fn print_failed_to_list_remote_directory(directory: &String, error: &str)
+{
+	let mut stdout = io::stdout();
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+	let _ = queue!(stdout, Print("\n[WARN] "));
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+	let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+	let _ = queue!(stdout, Print(format!("Failed to list content of directory \"{directory}\", ({error})\n")));
+	let _ = stdout.flush();
+}
+
+fn print_unparseable_remote_entry(directory: &String, line: &String, error: &str)
+{
+	let mut stdout = io::stdout();
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+	let _ = queue!(stdout, Print("\n[WARN] "));
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+	let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+	let _ = queue!(stdout, Print(format!("Skipping an entry of remote directory \"{directory}\" that couldn't be parsed ({error}): {line}\n")));
+	let _ = stdout.flush();
 }
 
-fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<File>
+fn get_all_local_files_recursive_from(directory: &String, ignore_rules: &IgnoreRules, symlinks: SymlinkPolicy, skipped: &mut Vec<(String, String)>, args: &Args) -> (Vec<File>, bool)
 {
 	let mut current_directory = directory.clone();
 	let mut tree: Vec<File> = Vec::new();
+	let mut complete = true; //False if anything couldn't be listed, so missing files can't be trusted as deleted
 	
 	let mut directories: Vec<String> = Vec::new();
 	let mut i: usize = 0;
 
+	//Where every listed directory really is, so following a link never lists the same directory twice (or forever)
+	let mut listed_directories: HashSet<PathBuf> = HashSet::new();
+	if symlinks == SymlinkPolicy::Follow
+	{
+		if let Ok(value) = fs::canonicalize(directory)
+		{
+			listed_directories.insert(value);
+		}
+	}
+
 	loop
 	{
 		match list_local_directory(&current_directory) //Obtener un iterador que contiene todos los elementos dentro de un directorio
@@ -414,6 +908,7 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 								Err(_) =>
 								{
 									println!("[ERROR] Failed to get file name of something in local directory");
+									complete = false;
 									if !args.continue_on_error
 									{
 										std::process::exit(1);
@@ -422,52 +917,79 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 								}
 							};
 
+							//Leftovers of interrupted transfers aren't part of the synced files
+							if is_temporary_file(&dir_entry.file_name().to_string_lossy())
+							{
+								continue;
+							}
+
 							match dir_entry.file_type()
 							{
 								Ok(value) =>
 								{
+									let relative_path = get_relative_path(&fullpath, directory);
+									let is_directory = value.is_dir() || (value.is_symlink() && symlinks == SymlinkPolicy::Follow && Path::new(&fullpath).is_dir());
+									if ignore_rules.is_ignored(&relative_path, is_directory)
+									{
+										continue;
+									}
+
 									//Si es un directorio añadir a la lista de directorios a explorar para explorar en otra iteración.
 									if value.is_dir()
 									{
+										//Only used to show it, directories are compared by whether they exist or not
+										let date_modified: DateTime<Utc> = match dir_entry.metadata()
+										{
+											Ok(metadata) =>
+											{
+												match metadata.modified()
+												{
+													Ok(value) => value.into(),
+													Err(_) => Utc::now(),
+												}
+											},
+											Err(_) => Utc::now(),
+										};
+
+										tree.push
+										(
+											File
+											{
+												directory: current_directory.clone(),
+												fullpath: fullpath.clone(),
+												relative_path: relative_path.clone(),
+												date_modified,
+												date_is_exact: true,
+												size: 0,
+												hash: None,
+												is_directory: true,
+												symlink_target: None,
+											}
+										);
+										if symlinks == SymlinkPolicy::Follow
+										{
+											if let Ok(value) = fs::canonicalize(&fullpath)
+											{
+												listed_directories.insert(value);
+											}
+										}
 										directories.push(fullpath);
 									}
 									else if value.is_file()
 									{
 										//Conseguir la fecha modificada
-										let date_modified = match dir_entry.metadata()
+										let (date_modified, size) = match dir_entry.metadata()
 										{
 											Ok(metadata) =>
 											{
-												match metadata.modified()
+												let date_modified = match metadata.modified()
 												{
 													Ok(modified) =>
 													{
 														let date_modified: DateTime<Utc> = modified.into();
-														let date_modified = match date_modified.with_second(0)
+														let date_modified = match date_modified.with_nanosecond(0)
 														{
-															Some(value) =>
-															{
-																match value.with_nanosecond(0)
-																{
-																	Some(value) => value,
-																	None =>
-																	{
-																		let mut stdout = io::stdout();
-																		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-																		let _ = queue!(stdout, SetForegroundColor(Color::Red));
-																		let _ = queue!(stdout, Print("\n[ERROR] "));
-																		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-																		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-																		let _ = queue!(stdout, Print(format!("Failed to trim local file nanoseconds!\n")));
-																		let _ = stdout.flush();
-																		if !args.continue_on_error
-																		{
-																			std::process::exit(1);
-																		}
-																		continue;
-																	}
-																}
-															},
+															Some(value) => value,
 															None =>
 															{
 																let mut stdout = io::stdout();
@@ -476,8 +998,9 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 																let _ = queue!(stdout, Print("\n[ERROR] "));
 																let _ = queue!(stdout, SetAttribute(Attribute::Reset));
 																let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-																let _ = queue!(stdout, Print(format!("Failed to trim local file seconds!\n")));
+																let _ = queue!(stdout, Print("Failed to trim local file nanoseconds!\n"));
 																let _ = stdout.flush();
+																complete = false;
 																if !args.continue_on_error
 																{
 																	std::process::exit(1);
@@ -498,13 +1021,15 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 														let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 														let _ = queue!(stdout, Print(format!("Failed to get last modified time of file in local directory \"{}\", {}", fullpath, error)));
 														let _ = stdout.flush();
+														complete = false;
 														if !args.continue_on_error
 														{
 															std::process::exit(1);
 														}
 														continue;
 													}
-												}
+												};
+												(date_modified, metadata.len())
 											},
 											Err(error) =>
 											{
@@ -516,6 +1041,7 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 												let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 												let _ = queue!(stdout, Print(format!("Failed to get metadata of file in local directory \"{}\", {}", fullpath, error)));
 												let _ = stdout.flush();
+												complete = false;
 												if !args.continue_on_error
 												{
 													std::process::exit(1);
@@ -524,16 +1050,6 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 											}
 										};
 
-										let relative_path = fullpath.clone().replacen(directory, "", 1).to_string();
-										let relative_path = if relative_path.starts_with("/")
-										{
-											relative_path
-										}
-										else
-										{
-											format!("/{relative_path}")
-										};
-
 										//Añadir al árbol de archivos que en realidad no es un árbol y es una lista nomás.
 										tree.push
 										(
@@ -541,12 +1057,36 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 											{
 												directory: current_directory.clone(),
 												fullpath: fullpath.clone(),
-												relative_path: relative_path,
-												date_modified: date_modified,
+												relative_path,
+												date_modified,
+												date_is_exact: true,
+												size,
+												hash: None,
+												is_directory: false,
+												symlink_target: None,
 												//handler: FileHandler::LocalFile,
 											}
 										);
 									}
+									else if value.is_symlink()
+									{
+										match get_local_symlink_entry(&fullpath, &relative_path, &current_directory, symlinks, &mut listed_directories)
+										{
+											Ok(file) =>
+											{
+												if file.is_directory
+												{
+													directories.push(fullpath);
+												}
+												tree.push(file);
+											},
+											Err(reason) =>
+											{
+												print_skipped_symlink(&relative_path, &reason);
+												skipped.push((relative_path, reason));
+											}
+										}
+									}
 								},
 								Err(error) =>
 								{
@@ -558,6 +1098,7 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 									let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 									let _ = queue!(stdout, Print(format!("Failed to get file type of something in local directory! ({})\n", error)));
 									let _ = stdout.flush();
+									complete = false;
 									if !args.continue_on_error
 									{
 										std::process::exit(1);
@@ -575,6 +1116,7 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 							let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 							let _ = queue!(stdout, Print(format!("Failed to access something in local directory! ({})\n", error)));
 							let _ = stdout.flush();
+							complete = false;
 							if !args.continue_on_error
 							{
 								std::process::exit(1);
@@ -585,6 +1127,7 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 			},
 			None =>
 			{
+				complete = false;
 				let mut stdout = io::stdout();
 				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
@@ -605,18 +1148,123 @@ fn get_all_local_files_recursive_from(directory: &String, args: &Args) -> Vec<Fi
 		i += 1;
 	}
 
-	tree
+	(tree, complete)
 }
 
-fn list_local_directory(directory: &String) -> Option<fs::ReadDir>
+//Returns what the link has to be synced as, or why it can't be.
+fn get_local_symlink_entry(fullpath: &String, relative_path: &str, current_directory: &str, symlinks: SymlinkPolicy, listed_directories: &mut HashSet<PathBuf>) -> Result<File, String>
 {
-	match fs::read_dir(directory)
+	match symlinks
 	{
-		Ok(value) => Some(value),
-		Err(_) => None,
+		SymlinkPolicy::Skip => Err(String::from("symbolic link")),
+		SymlinkPolicy::Preserve =>
+		{
+			let symlink_target = match fs::read_link(fullpath)
+			{
+				Ok(value) => value.to_string_lossy().to_string(),
+				Err(error) => return Err(format!("couldn't read the link, {error}")),
+			};
+			let date_modified: DateTime<Utc> = match fs::symlink_metadata(fullpath)
+			{
+				Ok(metadata) =>
+				{
+					match metadata.modified()
+					{
+						Ok(value) => value.into(),
+						Err(_) => Utc::now(),
+					}
+				},
+				Err(_) => Utc::now(),
+			};
+
+			Ok
+			(
+				File
+				{
+					directory: current_directory.to_string(),
+					fullpath: fullpath.to_string(),
+					relative_path: relative_path.to_string(),
+					date_modified,
+					date_is_exact: true,
+					size: 0,
+					hash: None,
+					is_directory: false,
+					symlink_target: Some(symlink_target),
+				}
+			)
+		},
+		SymlinkPolicy::Follow =>
+		{
+			let metadata = match fs::metadata(fullpath)
+			{
+				Ok(value) => value,
+				Err(_) => return Err(String::from("broken link")),
+			};
+
+			if metadata.is_dir()
+			{
+				let canonical_path = match fs::canonicalize(fullpath)
+				{
+					Ok(value) => value,
+					Err(error) => return Err(format!("couldn't follow the link, {error}")),
+				};
+				if !listed_directories.insert(canonical_path)
+				{
+					return Err(String::from("link to a directory that's already synced, it would loop"));
+				}
+			}
+			else if !metadata.is_file()
+			{
+				return Err(String::from("link to something that isn't a file or a directory"));
+			}
+
+			let date_modified: DateTime<Utc> = match metadata.modified()
+			{
+				Ok(value) => value.into(),
+				Err(error) => return Err(format!("couldn't get the modified date, {error}")),
+			};
+			let date_modified = match date_modified.with_nanosecond(0)
+			{
+				Some(value) => value,
+				None => date_modified,
+			};
+
+			Ok
+			(
+				File
+				{
+					directory: current_directory.to_string(),
+					fullpath: fullpath.to_string(),
+					relative_path: relative_path.to_string(),
+					date_modified,
+					date_is_exact: true,
+					size: if metadata.is_dir() { 0 } else { metadata.len() },
+					hash: None,
+					is_directory: metadata.is_dir(),
+					symlink_target: None,
+				}
+			)
+		},
 	}
 }
 
+fn print_skipped_symlink(relative_path: &String, reason: &str)
+{
+	let mut stdout = io::stdout();
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+	let _ = queue!(stdout, Print("[WARN] "));
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+	let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+	let _ = queue!(stdout, Print(format!("Skipping \"{relative_path}\" ({reason})\n")));
+	let _ = stdout.flush();
+}
+
+fn list_local_directory(directory: &String) -> Option<fs::ReadDir>
+{
+	fs::read_dir(directory).ok()
+}
+
 fn make_local_backup(all_local_files: &Vec<File>, sync_location: &SyncLocation) -> bool
 {
 	let backup_folder = format!("{}/backups/{}", get_program_folder(), sync_location.name_encoded);
@@ -629,7 +1277,7 @@ fn make_local_backup(all_local_files: &Vec<File>, sync_location: &SyncLocation)
 		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 		let _ = queue!(stdout, Print(" Preparing backup folder: "));
 		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-		let _ = queue!(stdout, Print(format!("{backup_folder}")));
+		let _ = queue!(stdout, Print(&backup_folder));
 		let _ = stdout.flush();
 	}
 
@@ -697,12 +1345,19 @@ fn make_local_backup(all_local_files: &Vec<File>, sync_location: &SyncLocation)
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 			let _ = queue!(stdout, Print(" Backing up: "));
 			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-			let _ = queue!(stdout, Print(format!("{}", &file.relative_path)));
+			let _ = queue!(stdout, Print(&file.relative_path));
 			let _ = stdout.flush();
 		}
 
 		let backup_path = format!("{backup_folder}{}", &file.relative_path);
-		let backup_directory = format!("{backup_folder}{}", get_relative_directory(file, &sync_location.local_path));
+		let backup_directory = if file.is_directory
+		{
+			backup_path.clone()
+		}
+		else
+		{
+			format!("{backup_folder}{}", get_relative_directory(file, &sync_location.local_path))
+		};
 
 		match fs::create_dir_all(&backup_directory)
 		{
@@ -718,8 +1373,24 @@ fn make_local_backup(all_local_files: &Vec<File>, sync_location: &SyncLocation)
 			}
 		}
 
+		if file.is_directory
+		{
+			let _ = queue!(stdout, SetForegroundColor(Color::Green));
+			let _ = queue!(stdout, Print(" (done!)"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = stdout.flush();
+			continue;
+		}
+
+		//fs::copy would copy what the link points to, so the link itself is made again
+		let result = match &file.symlink_target
+		{
+			Some(symlink_target) => make_local_symlink(symlink_target, &backup_path),
+			None => fs::copy(&file.fullpath, &backup_path).map(|_| ()),
+		};
+
 		//println!("{}\n{}\n\n", &file.fullpath, &backup_path);
-		match fs::copy(&file.fullpath, &backup_path)
+		match result
 		{
 			Ok(_) =>
 			{
@@ -746,134 +1417,378 @@ fn make_local_backup(all_local_files: &Vec<File>, sync_location: &SyncLocation)
 	true
 }
 
-fn link_all_files(all_remote_files: &Vec<File>, all_local_files: &Vec<File>, sync_location: &SyncLocation) -> Vec<LinkedFile>
+//Files are matched by relative path through a map and moved into their LinkedFile, so big trees don't compare or copy every file against every other.
+//Pairs go first, then what's only on the remote and then what's only local, each in the order they were listed.
+//Names written differently on each side (NFC and NFD, or in a different case with case_insensitive) are linked too, and keep the name they have on the remote.
+fn link_all_files(all_remote_files: Vec<File>, all_local_files: Vec<File>, remote_path: &String, local_path: &String, sync_state: &SyncState, case_insensitive: bool, unicode_normalization: NormalizationForm) -> Vec<LinkedFile>
 {
-	let mut all_linked_files = Vec::with_capacity(all_remote_files.len());
+	let synced_directories = get_directories_with_synced_contents(sync_state);
+
+	let mut local_indexes: HashMap<&str, usize> = HashMap::with_capacity(all_local_files.len());
+	for (i, local_file) in all_local_files.iter().enumerate()
+	{
+		local_indexes.insert(local_file.relative_path.as_str(), i);
+	}
+
+	let mut remote_matches: Vec<Option<usize>> = Vec::with_capacity(all_remote_files.len());
+	for remote_file in &all_remote_files
+	{
+		remote_matches.push(local_indexes.get(remote_file.relative_path.as_str()).copied());
+	}
+	drop(local_indexes);
+
+	if case_insensitive || unicode_normalization != NormalizationForm::None
+	{
+		match_written_differently(&all_remote_files, &all_local_files, &mut remote_matches, case_insensitive, unicode_normalization);
+	}
+
+	//Taken out of here as they get linked
+	let mut remote_files: Vec<Option<File>> = all_remote_files.into_iter().map(Some).collect();
+	let mut local_files: Vec<Option<File>> = all_local_files.into_iter().map(Some).collect();
+
+	let mut all_linked_files = Vec::with_capacity(remote_files.len().max(local_files.len()));
 
 	//Linkear todos los que tienen equivalentes en ambos lados
-	for remote_file in all_remote_files
+	for (i, local_index) in remote_matches.iter().enumerate()
 	{
-		for local_file in all_local_files
+		let local_index = match local_index
+		{
+			Some(value) => *value,
+			None => continue,
+		};
+
+		let (remote_file, local_file) = match (remote_files[i].take(), local_files[local_index].take())
+		{
+			(Some(remote_file), Some(local_file)) => (remote_file, local_file),
+			_ => continue,
+		};
+
+		//Neither can replace the other without deleting something, so it's left for the user to fix
+		let sync_veredict = if remote_file.is_directory != local_file.is_directory || remote_file.symlink_target.is_some() != local_file.symlink_target.is_some()
+		{
+			SyncVeredict::Skip
+		}
+		else
 		{
-			if remote_file.relative_path == local_file.relative_path
+			SyncVeredict::NotDecidedYet
+		};
+
+		all_linked_files.push
+		(
+			LinkedFile
 			{
-				all_linked_files.push
-				(
-					LinkedFile
-					{
-						relative_path: remote_file.relative_path.clone(),
-						relative_directory: get_relative_directory(remote_file, &sync_location.remote_path),
-						local_file: Some(local_file.clone()),
-						remote_file: Some(remote_file.clone()),
-						sync_veredict: SyncVeredict::NotDecidedYet,
-					}
-				)
+				relative_path: remote_file.relative_path.clone(),
+				relative_directory: get_relative_directory(&remote_file, remote_path),
+				local_relative_path: None,
+				last_synced: get_last_synced(&remote_file.relative_path, &remote_file, sync_state, &synced_directories),
+				local_file: Some(local_file),
+				remote_file: Some(remote_file),
+				sync_veredict,
+				conflict_resolution: None,
 			}
-		}
+		)
 	}
 
+	//New files inside a directory that was linked to one written differently go inside that one, instead of creating it again written their way
+	let (local_to_remote_directories, remote_to_local_directories) = get_renamed_directories(&all_linked_files);
+
 	//Encontrar archivos que estén en el remote pero no estén en local
-	for remote_file in all_remote_files
+	for remote_file in remote_files.into_iter().flatten()
 	{
-		let mut found = false;
-		for linked_file in &all_linked_files
-		{
-			let linked_remote_file = match &linked_file.remote_file
-			{
-				Some(value) => value,
-				None => continue,
-			};
-
-			if remote_file.fullpath == linked_remote_file.fullpath
+		all_linked_files.push
+		(
+			LinkedFile
 			{
-				found = true;
-				break;
+				relative_path: remote_file.relative_path.clone(),
+				relative_directory: get_relative_directory(&remote_file, remote_path),
+				local_relative_path: rename_parent_directory(&remote_file.relative_path, &remote_to_local_directories),
+				last_synced: get_last_synced(&remote_file.relative_path, &remote_file, sync_state, &synced_directories),
+				local_file: None,
+				remote_file: Some(remote_file),
+				sync_veredict: SyncVeredict::NotDecidedYet,
+				conflict_resolution: None,
 			}
-		}
-
-		if !found
-		{
-			all_linked_files.push
-			(
-				LinkedFile
-				{
-					relative_path: remote_file.relative_path.clone(),
-					relative_directory: get_relative_directory(remote_file, &sync_location.remote_path),
-					local_file: None,
-					remote_file: Some(remote_file.clone()),
-					sync_veredict: SyncVeredict::DownloadToLocal,
-				}
-			)
-		}
+		)
 	}
 
 	//Encontrar archivos que estén en local pero no estén en remote
-	for local_file in all_local_files
+	for local_file in local_files.into_iter().flatten()
 	{
-		let mut found = false;
-		for linked_file in &all_linked_files
+		let (relative_path, relative_directory) = match rename_parent_directory(&local_file.relative_path, &local_to_remote_directories)
 		{
-			let linked_remote_file = match &linked_file.local_file
+			Some(relative_path) => (relative_path.clone(), get_parent_directory(&relative_path)),
+			None => (local_file.relative_path.clone(), get_relative_directory(&local_file, local_path)),
+		};
+
+		all_linked_files.push
+		(
+			LinkedFile
 			{
-				Some(value) => value,
-				None => continue,
-			};
+				last_synced: get_last_synced(&relative_path, &local_file, sync_state, &synced_directories),
+				relative_path,
+				relative_directory,
+				local_relative_path: None,
+				local_file: Some(local_file),
+				remote_file: None,
+				sync_veredict: SyncVeredict::NotDecidedYet,
+				conflict_resolution: None,
+			}
+		)
+	}
+
+	mark_case_collisions(&mut all_linked_files);
 
-			if local_file.fullpath == linked_remote_file.fullpath
+	all_linked_files
+}
+
+//Directories linked to one written differently on the other side, as local to remote and remote to local relative paths
+fn get_renamed_directories(all_linked_files: &Vec<LinkedFile>) -> (HashMap<String, String>, HashMap<String, String>)
+{
+	let mut local_to_remote: HashMap<String, String> = HashMap::new();
+	let mut remote_to_local: HashMap<String, String> = HashMap::new();
+	for linked_file in all_linked_files
+	{
+		if let (Some(local_file), Some(remote_file)) = (&linked_file.local_file, &linked_file.remote_file)
+		{
+			if local_file.is_directory && remote_file.is_directory && local_file.relative_path != remote_file.relative_path
 			{
-				found = true;
-				break;
+				local_to_remote.insert(local_file.relative_path.clone(), remote_file.relative_path.clone());
+				remote_to_local.insert(remote_file.relative_path.clone(), local_file.relative_path.clone());
 			}
 		}
+	}
+
+	(local_to_remote, remote_to_local)
+}
+
+//The path with its deepest parent directory found in renamed_directories written like it says, None if none of its parents is there
+fn rename_parent_directory(relative_path: &str, renamed_directories: &HashMap<String, String>) -> Option<String>
+{
+	if renamed_directories.is_empty()
+	{
+		return None;
+	}
+
+	let mut end = relative_path.len();
+	loop
+	{
+		end = match relative_path[..end].rfind('/')
+		{
+			Some(0) | None => return None,
+			Some(value) => value,
+		};
 
-		if !found
+		if let Some(renamed) = renamed_directories.get(&relative_path[..end])
 		{
-			all_linked_files.push
-			(
-				LinkedFile
-				{
-					relative_path: local_file.relative_path.clone(),
-					relative_directory: get_relative_directory(local_file, &sync_location.local_path),
-					local_file: Some(local_file.clone()),
-					remote_file: None,
-					sync_veredict: SyncVeredict::UploadToRemote,
-				}
-			)
+			return Some(format!("{renamed}{}", &relative_path[end..]));
 		}
 	}
+}
 
-	all_linked_files
+fn get_parent_directory(relative_path: &str) -> String
+{
+	match relative_path.rfind('/')
+	{
+		Some(0) | None => String::from("/"),
+		Some(index) => relative_path[..index].to_string(),
+	}
 }
 
-fn set_sync_veredicts(all_linked_files: Vec<LinkedFile>, args: &Args) -> Vec<LinkedFile>
+//Only what didn't match exactly is matched by how it's written, so files that exist written both ways on both sides keep their own pair
+fn match_written_differently(all_remote_files: &[File], all_local_files: &[File], remote_matches: &mut [Option<usize>], case_insensitive: bool, unicode_normalization: NormalizationForm)
 {
-	let mut new_linked_files_list: Vec<LinkedFile> = Vec::with_capacity(all_linked_files.len());
-	
-	for linked_file in &all_linked_files
+	let mut matched_locally = vec![false; all_local_files.len()];
+	for local_index in remote_matches.iter().flatten()
 	{
-		let mut linked_file = linked_file.clone();
+		matched_locally[*local_index] = true;
+	}
 
-		if linked_file.sync_veredict != SyncVeredict::NotDecidedYet
+	let mut local_indexes: HashMap<Cow<str>, usize> = HashMap::new();
+	for (i, local_file) in all_local_files.iter().enumerate()
+	{
+		if !matched_locally[i]
 		{
-			new_linked_files_list.push(linked_file);
-			continue;
+			local_indexes.entry(get_matching_key(&local_file.relative_path, case_insensitive, unicode_normalization)).or_insert(i);
 		}
+	}
+
+	if local_indexes.is_empty()
+	{
+		return;
+	}
 
-		if linked_file.local_file.is_none() && linked_file.remote_file.is_some()
+	for (remote_file, remote_match) in all_remote_files.iter().zip(remote_matches.iter_mut())
+	{
+		if remote_match.is_none()
 		{
-			linked_file.sync_veredict = SyncVeredict::DownloadToLocal;
+			*remote_match = local_indexes.remove(&get_matching_key(&remote_file.relative_path, case_insensitive, unicode_normalization));
 		}
-		else if linked_file.local_file.is_some() && linked_file.remote_file.is_none()
+	}
+}
+
+//Only used to match files, each side keeps using its own name for everything else
+fn get_matching_key(relative_path: &str, case_insensitive: bool, unicode_normalization: NormalizationForm) -> Cow<'_, str>
+{
+	match normalization::normalize(relative_path, unicode_normalization)
+	{
+		Cow::Borrowed(value) => case::get_matching_key(value, case_insensitive),
+		Cow::Owned(value) => Cow::Owned(case::get_matching_key(&value, case_insensitive).into_owned()),
+	}
+}
+
+//Files that would be the same file on a case-insensitive filesystem, either because both are on the same side or because one was renamed
+//on a side where case doesn't matter. They're left as conflicts even when case matters here, the other side (or the next device) might
+//not care and one would replace the other.
+fn mark_case_collisions(all_linked_files: &mut [LinkedFile])
+{
+	let collisions: Vec<Vec<String>> = case::find_case_collisions(all_linked_files.iter().map(|linked_file| linked_file.relative_path.as_str()))
+		.into_iter()
+		.map(|group| group.into_iter().map(String::from).collect())
+		.collect();
+
+	let mut colliding: HashSet<&str> = HashSet::new();
+	for group in &collisions
+	{
+		print_case_collision(group);
+		for relative_path in group
 		{
-			linked_file.sync_veredict = SyncVeredict::UploadToRemote;
+			colliding.insert(relative_path.as_str());
 		}
-		else if linked_file.local_file.is_some() && linked_file.remote_file.is_some()
+	}
+
+	if colliding.is_empty()
+	{
+		return;
+	}
+
+	for linked_file in all_linked_files.iter_mut()
+	{
+		if colliding.contains(linked_file.relative_path.as_str())
 		{
-			let local_date_modified = match &linked_file.local_file
-			{
-				Some(file) => file.date_modified,
-				None =>
-				{
+			linked_file.sync_veredict = SyncVeredict::Conflict;
+			linked_file.conflict_resolution = Some(ConflictResolution::CaseCollision);
+		}
+	}
+}
+
+fn print_case_collision(group: &[String])
+{
+	let names: Vec<String> = group.iter().map(|relative_path| format!("\"{relative_path}\"")).collect();
+
+	let mut stdout = io::stdout();
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+	let _ = queue!(stdout, Print("\n[WARN] "));
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+	let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+	let _ = queue!(stdout, Print(format!("{} only differ in case and would be the same file on Windows or macOS, they're left as they are until they're renamed.\n", names.join(", "))));
+	let _ = stdout.flush();
+}
+
+//Every directory that has something synced inside, at any depth.
+fn get_directories_with_synced_contents(sync_state: &SyncState) -> HashSet<&str>
+{
+	let mut directories: HashSet<&str> = HashSet::new();
+	for relative_path in sync_state.files.keys()
+	{
+		let mut path = relative_path.as_str();
+		loop
+		{
+			path = match path.rfind('/')
+			{
+				Some(0) | None => break,
+				Some(value) => &path[..value],
+			};
+
+			//Its parents were already added by whatever added it
+			if !directories.insert(path)
+			{
+				break;
+			}
+		}
+	}
+	directories
+}
+
+//Directories weren't remembered by older versions, but one with synced files inside was synced too.
+fn get_last_synced(relative_path: &String, file: &File, sync_state: &SyncState, synced_directories: &HashSet<&str>) -> Option<SyncedFileState>
+{
+	if let Some(value) = sync_state.files.get(relative_path)
+	{
+		return Some(value.clone());
+	}
+
+	if !file.is_directory || !synced_directories.contains(relative_path.as_str())
+	{
+		return None;
+	}
+
+	Some
+	(
+		SyncedFileState
+		{
+			local_date_modified: file.date_modified.timestamp(),
+			remote_date_modified: file.date_modified.timestamp(),
+			local_size: None,
+			remote_size: None,
+			hash: None,
+		}
+	)
+}
+
+//Deletions are only propagated when the listing of the side where the file is missing is complete, otherwise a directory that failed to list would look deleted.
+fn set_sync_veredicts(all_linked_files: Vec<LinkedFile>, remote_listing_complete: bool, local_listing_complete: bool, compare_mode: CompareMode, direction: SyncDirection, args: &Args) -> Vec<LinkedFile>
+{
+	let mut new_linked_files_list: Vec<LinkedFile> = Vec::with_capacity(all_linked_files.len());
+	
+	for mut linked_file in all_linked_files
+	{
+		let mut same_content = false;
+
+		if linked_file.sync_veredict != SyncVeredict::NotDecidedYet
+		{
+			new_linked_files_list.push(linked_file);
+			continue;
+		}
+
+		if is_linked_directory(&linked_file) || is_linked_symlink(&linked_file)
+		{
+			//Directories only exist or not, their dates change with whatever happens inside them. Links are the same, changing where they point to isn't synced
+			linked_file.sync_veredict = match (&linked_file.local_file, &linked_file.remote_file, &linked_file.last_synced)
+			{
+				(Some(_), Some(_), _) => SyncVeredict::DoNothing,
+				(None, Some(_), Some(_)) if local_listing_complete => SyncVeredict::DeleteRemote,
+				(None, Some(_), _) => SyncVeredict::DownloadToLocal,
+				(Some(_), None, Some(_)) if remote_listing_complete => SyncVeredict::DeleteLocal,
+				_ => SyncVeredict::UploadToRemote,
+			};
+			same_content = linked_file.sync_veredict == SyncVeredict::DoNothing;
+		}
+		else if linked_file.local_file.is_none() && linked_file.remote_file.is_some()
+		{
+			//If it was synced before and the remote didn't change since then, it was deleted locally
+			linked_file.sync_veredict = match (&linked_file.last_synced, &linked_file.remote_file)
+			{
+				(Some(last_synced), Some(remote_file)) if local_listing_complete && !changed_since_last_sync(remote_file, last_synced.remote_date_modified, last_synced.remote_size, &last_synced.hash, compare_mode) => SyncVeredict::DeleteRemote,
+				_ => SyncVeredict::DownloadToLocal,
+			};
+		}
+		else if linked_file.local_file.is_some() && linked_file.remote_file.is_none()
+		{
+			//If it was synced before and the local file didn't change since then, it was deleted on the remote
+			linked_file.sync_veredict = match (&linked_file.last_synced, &linked_file.local_file)
+			{
+				(Some(last_synced), Some(local_file)) if remote_listing_complete && !changed_since_last_sync(local_file, last_synced.local_date_modified, last_synced.local_size, &last_synced.hash, compare_mode) => SyncVeredict::DeleteLocal,
+				_ => SyncVeredict::UploadToRemote,
+			};
+		}
+		else if linked_file.local_file.is_some() && linked_file.remote_file.is_some()
+		{
+			let local_file = match &linked_file.local_file
+			{
+				Some(file) => file,
+				None =>
+				{
 					println!("[ERROR] somehow local file is none after comprobing that it's not??");
 					if !args.continue_on_error
 					{
@@ -882,9 +1797,9 @@ fn set_sync_veredicts(all_linked_files: Vec<LinkedFile>, args: &Args) -> Vec<Lin
 					continue;
 				}
 			};
-			let remote_date_modified = match &linked_file.remote_file
+			let remote_file = match &linked_file.remote_file
 			{
-				Some(file) => file.date_modified,
+				Some(file) => file,
 				None =>
 				{
 					println!("[ERROR] somehow remote file is none after comprobing that it's not??");
@@ -898,17 +1813,71 @@ fn set_sync_veredicts(all_linked_files: Vec<LinkedFile>, args: &Args) -> Vec<Lin
 
 			//println!("l: {}, r: {}, {}", local_date_modified.timestamp(), remote_date_modified.timestamp(), linked_file.relative_path);
 
-			if local_date_modified.timestamp() == remote_date_modified.timestamp()
+			let local_timestamp = local_file.date_modified.timestamp();
+			let remote_timestamp = remote_file.date_modified.timestamp();
+
+			//Dates that came from LIST only have minutes, so the seconds of the local date can't be compared against them
+			let same_date = if remote_file.date_is_exact
 			{
-				linked_file.sync_veredict = SyncVeredict::DoNothing;
+				local_timestamp == remote_timestamp
 			}
-			else if local_date_modified.timestamp() < remote_date_modified.timestamp()
+			else
+			{
+				local_timestamp - local_timestamp.rem_euclid(60) == remote_timestamp - remote_timestamp.rem_euclid(60)
+			};
+			let same_size = local_file.size == remote_file.size;
+
+			same_content = match compare_mode
+			{
+				CompareMode::Mtime => same_date,
+				CompareMode::MtimeSize => same_date && same_size,
+				CompareMode::Checksum =>
+				{
+					match hash::compare_hashes(&local_file.hash, &remote_file.hash)
+					{
+						Some(same_hash) => same_size && same_hash,
+						None => same_date && same_size, //Couldn't be hashed, the best left is what mtime-size would do
+					}
+				}
+			};
+
+			if same_content
 			{
-				linked_file.sync_veredict = SyncVeredict::DownloadToLocal;
+				linked_file.sync_veredict = SyncVeredict::DoNothing;
 			}
 			else
 			{
-				linked_file.sync_veredict = SyncVeredict::UploadToRemote;
+				match &linked_file.last_synced
+				{
+					Some(last_synced) =>
+					{
+						//Compare each side against how the last sync left it, to know which one actually changed
+						let local_changed = changed_since_last_sync(local_file, last_synced.local_date_modified, last_synced.local_size, &last_synced.hash, compare_mode);
+						let remote_changed = changed_since_last_sync(remote_file, last_synced.remote_date_modified, last_synced.remote_size, &last_synced.hash, compare_mode);
+
+						if local_changed && !remote_changed
+						{
+							linked_file.sync_veredict = SyncVeredict::UploadToRemote;
+						}
+						else if !local_changed && remote_changed
+						{
+							linked_file.sync_veredict = SyncVeredict::DownloadToLocal;
+						}
+						else if !local_changed && !remote_changed
+						{
+							//Dates differ, but both sides are exactly as the last sync left them
+							linked_file.sync_veredict = SyncVeredict::DoNothing;
+						}
+						else
+						{
+							linked_file.sync_veredict = SyncVeredict::Conflict;
+						}
+					},
+					None =>
+					{
+						linked_file.sync_veredict = get_newest_side_veredict(local_timestamp, remote_timestamp);
+					}
+				}
 			}
 		}
 		else
@@ -920,357 +1889,2662 @@ fn set_sync_veredicts(all_linked_files: Vec<LinkedFile>, args: &Args) -> Vec<Lin
 			}
 		}
 
+		linked_file.sync_veredict = get_directed_veredict(&linked_file, same_content, direction, remote_listing_complete, local_listing_complete);
+
 		new_linked_files_list.push(linked_file);
 	}
 
-	new_linked_files_list
+	keep_directories_with_contents(new_linked_files_list)
 }
 
-//Debe asegurarse de que ambas rutas provengan del mismo lugar.
-//Si es que file es de un remote, entonces sync_location_path debería venir de SyncLocation::remote_path,
-//Del mismo modo, si file es local, entonces sync_location_path debería venir de SyncLocation::local_path.
-fn get_relative_directory(file: &File, sync_location_path: &String) -> String
+//A directory deleted on one side but with new or changed files inside on the other side is created again instead, those files need it.
+fn keep_directories_with_contents(mut all_linked_files: Vec<LinkedFile>) -> Vec<LinkedFile>
 {
-	let relative_directory = file.directory.replacen(sync_location_path, "",1).to_string();
-	
-	if relative_directory.starts_with("/")
+	//Directories with something inside that isn't being deleted locally, and the same for the remote
+	let mut kept_locally: HashSet<String> = HashSet::new();
+	let mut kept_on_remote: HashSet<String> = HashSet::new();
+	for linked_file in &all_linked_files
 	{
-		relative_directory
+		let mut path = linked_file.relative_path.as_str();
+		loop
+		{
+			path = match path.rfind('/')
+			{
+				Some(0) | None => break,
+				Some(value) => &path[..value],
+			};
+
+			let mut added = false;
+			if linked_file.sync_veredict != SyncVeredict::DeleteLocal
+			{
+				added |= kept_locally.insert(path.to_string());
+			}
+			if linked_file.sync_veredict != SyncVeredict::DeleteRemote
+			{
+				added |= kept_on_remote.insert(path.to_string());
+			}
+
+			//Its parents were already added by whatever added it
+			if !added
+			{
+				break;
+			}
+		}
 	}
-	else
+
+	for linked_file in &mut all_linked_files
 	{
-		format!("/{relative_directory}")
+		if !is_linked_directory(linked_file)
+		{
+			continue;
+		}
+
+		linked_file.sync_veredict = match linked_file.sync_veredict
+		{
+			SyncVeredict::DeleteLocal if kept_locally.contains(&linked_file.relative_path) => SyncVeredict::UploadToRemote,
+			SyncVeredict::DeleteRemote if kept_on_remote.contains(&linked_file.relative_path) => SyncVeredict::DownloadToLocal,
+			other => other,
+		};
 	}
+
+	all_linked_files
 }
 
-fn sync_files(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, args: &Args)
+fn is_linked_symlink(linked_file: &LinkedFile) -> bool
 {
-	let mut report = Report::new();
-
-	do_nothing(all_linked_files, &mut report, args);
-	upload_to_remote(all_linked_files, sync_location, ftp_stream, &mut report, args);
-	download_to_local(all_linked_files, sync_location, ftp_stream, &mut report, args);
-
-	report.print();
+	match (&linked_file.local_file, &linked_file.remote_file)
+	{
+		(Some(file), _) | (None, Some(file)) => file.symlink_target.is_some(),
+		(None, None) => false,
+	}
 }
 
-fn upload_to_remote(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, report: &mut Report, args: &Args)
+fn is_linked_directory(linked_file: &LinkedFile) -> bool
 {
-	let mut stdout = io::stdout();
+	match (&linked_file.local_file, &linked_file.remote_file)
+	{
+		(Some(file), _) | (None, Some(file)) => file.is_directory,
+		(None, None) => false,
+	}
+}
 
+//Directories have to be created before what goes inside them, and deleted after it, deepest first.
+fn get_ordered_for_sync(all_linked_files: &Vec<LinkedFile>, sync_veredict: SyncVeredict) -> Vec<&LinkedFile>
+{
+	let mut files: Vec<&LinkedFile> = Vec::new();
+	let mut directories: Vec<&LinkedFile> = Vec::new();
 	for linked_file in all_linked_files
 	{
-		if linked_file.sync_veredict == SyncVeredict::UploadToRemote
+		if linked_file.sync_veredict != sync_veredict
 		{
-			{ //Imprimir bonito
-				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-				let _ = queue!(stdout, SetForegroundColor(Color::Green));
-				let _ = queue!(stdout, Print("\n->"));
-				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-				let _ = queue!(stdout, Print(" Uploading: "));
-				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-				let _ = queue!(stdout, Print(format!("{}", linked_file.relative_path)));
-				let _ = stdout.flush();
-			}
+			continue;
+		}
 
-			let remote_directory = format!("{}{}", sync_location.remote_path, linked_file.relative_directory);
-			let remote_fullpath = format!("{}{}", sync_location.remote_path, linked_file.relative_path);
-			//println!("{remote_directory}");
+		if is_linked_directory(linked_file)
+		{
+			directories.push(linked_file);
+		}
+		else
+		{
+			files.push(linked_file);
+		}
+	}
 
-			//Comprobar que el directorio existe
-			let directory_exists: bool = match ftp_stream.list(Some(remote_directory.as_str()))
-			{
-				Ok(_) => true,
-				Err(_) => false,
-			};
+	directories.sort_by_key(|linked_file| linked_file.relative_path.matches('/').count());
 
-			//Crear directorio si no existe
-			if !directory_exists
-			{
-				match ftp_stream.mkdir(&remote_directory)
-				{
-					Ok(_) => 
-					{
-						if args.log_level == LogLevel::Verbose
-						{
-							let _ = queue!(stdout, SetForegroundColor(Color::Cyan));
-							let _ = queue!(stdout, Print("\n[INFO] "));
-							let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-							let _ = queue!(stdout, Print(format!("Directory {} created on remote.\n", &remote_directory).as_str()));
-							let _ = stdout.flush();
-						}
-					},
-					Err(error) =>
-					{
-						report.errors += 1;
-						let _ = queue!(stdout, SetForegroundColor(Color::Red));
-						let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-						let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-						let _ = queue!(stdout, Print(format!("Failed to create remote directory ({}), {}\n", &remote_directory, error)));
-						let _ = stdout.flush();
+	if sync_veredict == SyncVeredict::DeleteLocal || sync_veredict == SyncVeredict::DeleteRemote
+	{
+		directories.reverse();
+		files.extend(directories);
+		files
+	}
+	else
+	{
+		directories.extend(files);
+		directories
+	}
+}
 
-						if !args.continue_on_error
-						{
-							std::process::exit(1);
-						}
-						continue;
-					}
-				}
-			}
+//Turns what a bidirectional sync would do into what the direction allows, changes made on the side that isn't the source are never synced back.
+//Mirrors don't care about how the last sync left the files, anything that's different gets replaced.
+fn get_directed_veredict(linked_file: &LinkedFile, same_content: bool, direction: SyncDirection, remote_listing_complete: bool, local_listing_complete: bool) -> SyncVeredict
+{
+	let sync_veredict = linked_file.sync_veredict;
 
-			//Cargar archivo local
-			let local_file = match &linked_file.local_file
+	match direction
+	{
+		SyncDirection::Bidirectional => sync_veredict,
+		SyncDirection::UploadOnly =>
+		{
+			match sync_veredict
 			{
-				Some(value) => value,
-				None =>
-				{
-					report.errors += 1;
-					let _ = queue!(stdout, SetForegroundColor(Color::Red));
-					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to access internal local file handler! ({})\n", &remote_directory)));
-					let _ = stdout.flush();
-
-					if !args.continue_on_error
-					{
-						std::process::exit(1);
-					}
-					continue;
-				}
-			};
-
-			let mut local_file_handler = match fs::File::open(local_file.fullpath.clone())
+				//The local version wins conflicts, and files deleted on the remote are uploaded again
+				SyncVeredict::UploadToRemote | SyncVeredict::Conflict | SyncVeredict::DeleteLocal => SyncVeredict::UploadToRemote,
+				SyncVeredict::DownloadToLocal | SyncVeredict::DeleteRemote => SyncVeredict::DoNothing,
+				_ => sync_veredict,
+			}
+		},
+		SyncDirection::DownloadOnly =>
+		{
+			match sync_veredict
 			{
-				Ok(value) => value,
-				Err(error) =>
-				{
-					report.errors += 1;
-					let _ = queue!(stdout, SetForegroundColor(Color::Red));
-					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to open local file!! ({}) {}\n", &remote_directory, error)));
-					let _ = stdout.flush();
-
-					if !args.continue_on_error
-					{
-						std::process::exit(1);
-					}
-					continue;
-				}
-			};
-
-			//Subir archivo a remote
-			match ftp_stream.put_file(&remote_fullpath, &mut local_file_handler)
+				SyncVeredict::DownloadToLocal | SyncVeredict::Conflict | SyncVeredict::DeleteRemote => SyncVeredict::DownloadToLocal,
+				SyncVeredict::UploadToRemote | SyncVeredict::DeleteLocal => SyncVeredict::DoNothing,
+				_ => sync_veredict,
+			}
+		},
+		SyncDirection::MirrorUp =>
+		{
+			match (sync_veredict, &linked_file.local_file)
 			{
-				Ok(_) => (),
-				Err(error) =>
-				{
-					report.errors += 1;
-					let _ = queue!(stdout, SetForegroundColor(Color::Red));
-					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to upload file to remote ({})\n{}\n", &remote_fullpath, error)));
-					let _ = stdout.flush();
-
-					if !args.continue_on_error
-					{
-						std::process::exit(1);
-					}
-					continue;
-				}
+				(SyncVeredict::NotDecidedYet, _) => sync_veredict,
+				_ if same_content => SyncVeredict::DoNothing,
+				(_, Some(_)) => SyncVeredict::UploadToRemote,
+				(_, None) if local_listing_complete => SyncVeredict::DeleteRemote,
+				(_, None) => SyncVeredict::DoNothing,
 			}
-
-			report.uploaded += 1;
-			{ //Imprimir bonito
-				let _ = queue!(stdout, SetForegroundColor(Color::Green));
-				let _ = queue!(stdout, Print(" (done!)"));
-				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-				let _ = stdout.flush();
+		},
+		SyncDirection::MirrorDown =>
+		{
+			match (sync_veredict, &linked_file.remote_file)
+			{
+				(SyncVeredict::NotDecidedYet, _) => sync_veredict,
+				_ if same_content => SyncVeredict::DoNothing,
+				(_, Some(_)) => SyncVeredict::DownloadToLocal,
+				(_, None) if remote_listing_complete => SyncVeredict::DeleteLocal,
+				(_, None) => SyncVeredict::DoNothing,
 			}
-		}
+		},
 	}
 }
 
-fn download_to_local(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, report: &mut Report, args: &Args)
+//Files with different sizes can't have the same content, so only the ones with the same size on both sides get hashed.
+fn hash_linked_files(all_linked_files: Vec<LinkedFile>, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, hash_cache: &mut LocalHashCache) -> Vec<LinkedFile>
 {
-	let mut stdout = io::stdout();
+	let mut new_linked_files_list: Vec<LinkedFile> = Vec::with_capacity(all_linked_files.len());
+
+	for mut linked_file in all_linked_files
+	{
+		let (local_file, remote_file) = match (&mut linked_file.local_file, &mut linked_file.remote_file)
+		{
+			(Some(local_file), Some(remote_file)) => (local_file, remote_file),
+			_ =>
+			{
+				new_linked_files_list.push(linked_file);
+				continue;
+			}
+		};
+
+		if local_file.size != remote_file.size || local_file.is_directory || remote_file.is_directory || local_file.symlink_target.is_some() || remote_file.symlink_target.is_some()
+		{
+			new_linked_files_list.push(linked_file);
+			continue;
+		}
+
+		//No need to ask the server for the hash again if the remote file is as the last sync left it
+		remote_file.hash = match &linked_file.last_synced
+		{
+			Some(last_synced) if last_synced.hash.is_some() && last_synced.remote_date_modified == remote_file.date_modified.timestamp() && last_synced.remote_size == Some(remote_file.size) => last_synced.hash.clone(),
+			_ => ftp::get_remote_hash(ftp_stream, server_features, &remote_file.fullpath),
+		};
+
+		//The local hash has to use the same algorithm to be comparable
+		let algorithm = match &remote_file.hash
+		{
+			Some(value) => HashAlgorithm::from_tagged_hash(value),
+			None => None,
+		};
+		let algorithm = match algorithm
+		{
+			Some(value) => value,
+			None => get_state_hash_algorithm(server_features),
+		};
+
+		local_file.hash = hash_cache.get_hash(&local_file.fullpath, local_file.size, local_file.date_modified.timestamp(), algorithm);
+
+		new_linked_files_list.push(linked_file);
+	}
+
+	new_linked_files_list
+}
+
+//The algorithm used for the hashes saved in the sync state, the same one the server uses so they can be compared with what it answers
+fn get_state_hash_algorithm(server_features: &ServerFeatures) -> HashAlgorithm
+{
+	match server_features.hash_command
+	{
+		Some((_, algorithm)) => algorithm,
+		None => HashAlgorithm::Md5,
+	}
+}
+
+//Compares one side of a file against how the last sync left it, sizes and hashes are only compared if the last sync saved them.
+fn changed_since_last_sync(file: &File, last_synced_date_modified: i64, last_synced_size: Option<u64>, last_synced_hash: &Option<String>, compare_mode: CompareMode) -> bool
+{
+	if file.date_modified.timestamp() != last_synced_date_modified
+	{
+		return true;
+	}
+
+	if compare_mode != CompareMode::Mtime
+	{
+		match last_synced_size
+		{
+			Some(size) if size != file.size => return true,
+			_ => (),
+		}
+	}
+
+	if compare_mode == CompareMode::Checksum
+	{
+		if let Some(false) = hash::compare_hashes(&file.hash, last_synced_hash)
+		{
+			return true;
+		}
+	}
+
+	false
+}
+
+//Prints what a sync would do grouped by action, returns true if anything would change.
+fn print_plan(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation) -> bool
+{
+	let mut stdout = io::stdout();
+	let groups =
+	[
+		(SyncVeredict::UploadToRemote, "->", Color::Green, "To upload"),
+		(SyncVeredict::DownloadToLocal, "<-", Color::Blue, "To download"),
+		(SyncVeredict::DeleteLocal, "xx", Color::Magenta, "To delete locally"),
+		(SyncVeredict::DeleteRemote, "xx", Color::Magenta, "To delete on remote"),
+		(SyncVeredict::Conflict, "!!", Color::Red, "Conflicts"),
+		(SyncVeredict::Skip, "--", Color::Yellow, "Skipped"),
+		(SyncVeredict::NotDecidedYet, "!!", Color::Red, "Undecided"),
+		(SyncVeredict::DoNothing, "--", Color::Yellow, "Unchanged"),
+	];
+
+	for (sync_veredict, symbol, color, title) in groups
+	{
+		let mut group: Vec<&LinkedFile> = Vec::new();
+		for linked_file in all_linked_files
+		{
+			if linked_file.sync_veredict == sync_veredict
+			{
+				group.push(linked_file);
+			}
+		}
+
+		if group.is_empty()
+		{
+			continue;
+		}
+
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(color));
+		let _ = queue!(stdout, Print(format!("\n{symbol}")));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!(" {title} ({}):\n", group.len())));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		if sync_veredict == SyncVeredict::Conflict
+		{
+			let _ = queue!(stdout, Print(format!("   (conflict_policy is \"{}\")\n", sync_location.conflict_policy.to_config_value())));
+		}
+
+		for linked_file in group
+		{
+			let local_description = match &linked_file.local_file
+			{
+				Some(file) => get_file_description(file),
+				None => String::from("(doesn't exist)"),
+			};
+			let remote_description = match &linked_file.remote_file
+			{
+				Some(file) => get_file_description(file),
+				None => String::from("(doesn't exist)"),
+			};
+
+			let _ = queue!(stdout, Print(format!("   {}\n", linked_file.relative_path)));
+			let _ = queue!(stdout, Print(format!("      Local:  {local_description}\n")));
+			let _ = queue!(stdout, Print(format!("      Remote: {remote_description}\n")));
+		}
+	}
+
+	let changes_pending = has_changes_pending(all_linked_files);
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	match changes_pending
+	{
+		true => { let _ = queue!(stdout, Print("\nDry run, nothing was changed. There are changes pending.\n")); },
+		false => { let _ = queue!(stdout, Print("\nDry run, nothing was changed. A sync wouldn't change anything either.\n")); },
+	}
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+	let _ = stdout.flush();
+
+	changes_pending
+}
+
+//Skipped files and case collisions are never synced, so counting them would leave changes pending forever
+fn has_changes_pending(all_linked_files: &[LinkedFile]) -> bool
+{
+	all_linked_files.iter().any(|linked_file| match linked_file.sync_veredict
+	{
+		SyncVeredict::DoNothing | SyncVeredict::Skip => false,
+		SyncVeredict::Conflict => linked_file.conflict_resolution != Some(ConflictResolution::CaseCollision),
+		_ => true,
+	})
+}
+
+fn get_file_description(file: &File) -> String
+{
+	if file.is_directory
+	{
+		return format!("{}, directory", file.date_modified.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"));
+	}
+
+	if let Some(symlink_target) = &file.symlink_target
+	{
+		return format!("{}, link to {symlink_target}", file.date_modified.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"));
+	}
+
+	format!("{}, {} bytes", file.date_modified.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"), file.size)
+}
+
+//Files that won't change aren't shown, there's nothing to decide about them.
+fn review_plan(mut all_linked_files: Vec<LinkedFile>) -> Vec<LinkedFile>
+{
+	let mut review_items: Vec<ReviewItem> = Vec::new();
+	let mut reviewed_files: Vec<usize> = Vec::new();
+
+	for (index, linked_file) in all_linked_files.iter().enumerate()
+	{
+		//Syncing either one would replace the other, they have to be renamed
+		if linked_file.conflict_resolution == Some(ConflictResolution::CaseCollision)
+		{
+			continue;
+		}
+
+		let action = match linked_file.sync_veredict
+		{
+			SyncVeredict::UploadToRemote => ReviewAction::Upload,
+			SyncVeredict::DownloadToLocal => ReviewAction::Download,
+			SyncVeredict::DeleteLocal => ReviewAction::DeleteLocal,
+			SyncVeredict::DeleteRemote => ReviewAction::DeleteRemote,
+			SyncVeredict::KeepBoth => ReviewAction::KeepBoth,
+			SyncVeredict::Conflict => ReviewAction::Conflict,
+			SyncVeredict::Skip | SyncVeredict::DoNothing | SyncVeredict::NotDecidedYet => continue,
+		};
+
+		review_items.push
+		(
+			ReviewItem
+			{
+				relative_path: linked_file.relative_path.clone(),
+				action,
+				local_description: linked_file.local_file.as_ref().map(get_file_description),
+				remote_description: linked_file.remote_file.as_ref().map(get_file_description),
+			}
+		);
+		reviewed_files.push(index);
+	}
+
+	if review_items.is_empty()
+	{
+		return all_linked_files;
+	}
+
+	let actions = tui::start_plan_review_blocking(&review_items);
+
+	for (index, action) in reviewed_files.into_iter().zip(actions)
+	{
+		let linked_file = &mut all_linked_files[index];
+		let was_conflict = linked_file.sync_veredict == SyncVeredict::Conflict;
+
+		linked_file.sync_veredict = match action
+		{
+			ReviewAction::Upload => SyncVeredict::UploadToRemote,
+			ReviewAction::Download => SyncVeredict::DownloadToLocal,
+			ReviewAction::DeleteLocal => SyncVeredict::DeleteLocal,
+			ReviewAction::DeleteRemote => SyncVeredict::DeleteRemote,
+			ReviewAction::KeepBoth => SyncVeredict::KeepBoth,
+			ReviewAction::Conflict => SyncVeredict::Conflict,
+			ReviewAction::Skip if was_conflict => SyncVeredict::Conflict,
+			ReviewAction::Skip => SyncVeredict::Skip,
+		};
+
+		//A conflict solved by hand still shows up as a conflict in the report
+		if was_conflict
+		{
+			linked_file.conflict_resolution = match action
+			{
+				ReviewAction::Upload => Some(ConflictResolution::KeepLocal),
+				ReviewAction::Download => Some(ConflictResolution::KeepRemote),
+				ReviewAction::KeepBoth => Some(ConflictResolution::KeepBoth),
+				ReviewAction::Skip => Some(ConflictResolution::Skip),
+				_ => None,
+			};
+		}
+	}
+
+	//Like when planning, a directory flipped to be deleted is kept if something inside it isn't being deleted too
+	keep_directories_with_contents(all_linked_files)
+}
+
+fn resolve_conflicts(all_linked_files: Vec<LinkedFile>, sync_location: &SyncLocation) -> Vec<LinkedFile>
+{
+	let mut new_linked_files_list: Vec<LinkedFile> = Vec::with_capacity(all_linked_files.len());
+
+	for mut linked_file in all_linked_files
+	{
+		if linked_file.sync_veredict != SyncVeredict::Conflict || linked_file.conflict_resolution.is_some()
+		{
+			new_linked_files_list.push(linked_file);
+			continue;
+		}
+
+		let (local_timestamp, remote_timestamp) = match (&linked_file.local_file, &linked_file.remote_file)
+		{
+			(Some(local_file), Some(remote_file)) => (local_file.date_modified.timestamp(), remote_file.date_modified.timestamp()),
+			_ =>
+			{
+				new_linked_files_list.push(linked_file);
+				continue;
+			}
+		};
+
+		let conflict_resolution = match sync_location.conflict_policy
+		{
+			ConflictPolicy::PreferLocal => ConflictResolution::KeepLocal,
+			ConflictPolicy::PreferRemote => ConflictResolution::KeepRemote,
+			ConflictPolicy::NewestWins =>
+			{
+				match get_newest_side_veredict(local_timestamp, remote_timestamp)
+				{
+					SyncVeredict::DownloadToLocal => ConflictResolution::KeepRemote,
+					_ => ConflictResolution::KeepLocal,
+				}
+			},
+			ConflictPolicy::KeepBoth => ConflictResolution::KeepBoth,
+			ConflictPolicy::Ask => ask_conflict_resolution(&linked_file),
+		};
+
+		linked_file.sync_veredict = match conflict_resolution
+		{
+			ConflictResolution::KeepLocal => SyncVeredict::UploadToRemote,
+			ConflictResolution::KeepRemote => SyncVeredict::DownloadToLocal,
+			ConflictResolution::KeepBoth => SyncVeredict::KeepBoth,
+			ConflictResolution::Skip | ConflictResolution::CaseCollision => SyncVeredict::Conflict,
+		};
+		linked_file.conflict_resolution = Some(conflict_resolution);
+
+		new_linked_files_list.push(linked_file);
+	}
+
+	new_linked_files_list
+}
+
+fn ask_conflict_resolution(linked_file: &LinkedFile) -> ConflictResolution
+{
+	let mut stdout = io::stdout();
+
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Red));
+		let _ = queue!(stdout, Print("\n!!"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Changed on both sides: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(format!("{}\n", linked_file.relative_path)));
+		if let Some(value) = &linked_file.local_file
+		{
+			let _ = queue!(stdout, Print(format!("   Local:  {}\n", value.date_modified.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"))));
+		}
+		if let Some(value) = &linked_file.remote_file
+		{
+			let _ = queue!(stdout, Print(format!("   Remote: {}\n", value.date_modified.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"))));
+		}
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, Print("   Keep [l]ocal, keep [r]emote, keep [b]oth or [s]kip? "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = stdout.flush();
+	}
+
+	let _ = terminal::enable_raw_mode();
+	let conflict_resolution = loop
+	{
+		let key_event = match event::read()
+		{
+			Ok(value) =>
+			{
+				match value.as_key_event()
+				{
+					Some(key_event) => key_event,
+					None => continue,
+				}
+			},
+			Err(_) => break ConflictResolution::Skip, //Nobody to ask
+		};
+
+		if key_event.kind != KeyEventKind::Press
+		{
+			continue;
+		}
+
+		match key_event.code
+		{
+			KeyCode::Char('l') => break ConflictResolution::KeepLocal,
+			KeyCode::Char('r') => break ConflictResolution::KeepRemote,
+			KeyCode::Char('b') => break ConflictResolution::KeepBoth,
+			KeyCode::Char('s') | KeyCode::Esc => break ConflictResolution::Skip,
+			_ => (),
+		}
+	};
+	let _ = terminal::disable_raw_mode();
+
+	let _ = queue!(stdout, Print(format!("{}\n", get_conflict_resolution_description(conflict_resolution))));
+	let _ = stdout.flush();
+
+	conflict_resolution
+}
+
+fn get_conflict_resolution_description(conflict_resolution: ConflictResolution) -> &'static str
+{
+	match conflict_resolution
+	{
+		ConflictResolution::KeepLocal => "kept local version",
+		ConflictResolution::KeepRemote => "kept remote version",
+		ConflictResolution::KeepBoth => "kept both versions",
+		ConflictResolution::Skip => "skipped",
+		ConflictResolution::CaseCollision => "only differs in case from another file, skipped",
+	}
+}
+
+fn get_newest_side_veredict(local_timestamp: i64, remote_timestamp: i64) -> SyncVeredict
+{
+	if local_timestamp < remote_timestamp
+	{
+		SyncVeredict::DownloadToLocal
+	}
+	else
+	{
+		SyncVeredict::UploadToRemote
+	}
+}
+
+//Debe asegurarse de que ambas rutas provengan del mismo lugar.
+//Si es que file es de un remote, entonces sync_location_path debería venir de SyncLocation::remote_path,
+//Del mismo modo, si file es local, entonces sync_location_path debería venir de SyncLocation::local_path.
+fn get_relative_path(fullpath: &str, root_directory: &str) -> String
+{
+	let relative_path = fullpath.replacen(root_directory, "", 1);
+	if relative_path.starts_with("/")
+	{
+		relative_path
+	}
+	else
+	{
+		format!("/{relative_path}")
+	}
+}
+
+fn get_relative_directory(file: &File, sync_location_path: &String) -> String
+{
+	let relative_directory = file.directory.replacen(sync_location_path, "",1).to_string();
+	
+	if relative_directory.starts_with("/")
+	{
+		relative_directory
+	}
+	else
+	{
+		format!("/{relative_directory}")
+	}
+}
+
+//Where the file is, or where it goes, on the local side. It's only written differently than relative_path when it's inside a directory
+//written differently on each side (see link_all_files).
+fn get_local_location(linked_file: &LinkedFile, sync_location: &SyncLocation) -> (String, String)
+{
+	match &linked_file.local_file
+	{
+		Some(local_file) => (local_file.directory.clone(), local_file.fullpath.clone()),
+		None =>
+		{
+			let relative_path = match &linked_file.local_relative_path
+			{
+				Some(value) => value,
+				None => &linked_file.relative_path,
+			};
+			(format!("{}{}", sync_location.local_path, get_parent_directory(relative_path)), format!("{}{}", sync_location.local_path, relative_path))
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sync_files(all_linked_files: Vec<LinkedFile>, skipped: Vec<(String, String)>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, hash_cache: &mut LocalHashCache, timezone_probe: Option<TimezoneProbe>, transfer_limits: &TransferLimits, args: &Args) -> bool
+{
+	let mut report = Report::new();
+	report.skipped = skipped;
+
+	for linked_file in &all_linked_files
+	{
+		if let Some(conflict_resolution) = linked_file.conflict_resolution
+		{
+			report.conflicts.push((linked_file.relative_path.clone(), conflict_resolution));
+		}
+	}
+
+	//Files that fail to sync keep how they were left by the previous sync, the rest get updated as they're synced
+	let mut new_sync_state = SyncState::new();
+	new_sync_state.timezone_probe = timezone_probe;
+	for linked_file in &all_linked_files
+	{
+		if let Some(last_synced) = &linked_file.last_synced
+		{
+			new_sync_state.files.insert(linked_file.relative_path.clone(), last_synced.clone());
+		}
+	}
+
+	//A failure stops the sync, but what was already synced is still remembered
+	let (all_linked_files, mut completed) = keep_both_versions(all_linked_files, sync_location, ftp_stream, server_features, &mut report, args);
+
+	let progress = get_sync_progress(&all_linked_files);
+
+	if completed
+	{
+		completed = do_nothing(&all_linked_files, &mut new_sync_state, &mut report, args);
+	}
+	if completed
+	{
+		completed = upload_to_remote(&all_linked_files, sync_location, ftp_stream, server_features, &mut new_sync_state, &mut report, transfer_limits, &progress, args);
+	}
+	if completed
+	{
+		completed = download_to_local(&all_linked_files, sync_location, ftp_stream, server_features, &mut new_sync_state, &mut report, transfer_limits, &progress, args);
+	}
+	if completed
+	{
+		completed = delete_from_local(&all_linked_files, sync_location, &mut new_sync_state, &mut report, args);
+	}
+	if completed
+	{
+		completed = delete_from_remote(&all_linked_files, sync_location, ftp_stream, server_features, &mut new_sync_state, &mut report, args);
+	}
+
+	report.print();
+
+	if sync_location.compare_mode == CompareMode::Checksum
+	{
+		remember_hashes(&all_linked_files, &mut new_sync_state, sync_location, server_features, hash_cache);
+	}
+
+	if !state::save_sync_state(sync_location, &new_sync_state)
+	{
+		let mut stdout = io::stdout();
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+		let _ = queue!(stdout, Print("[WARN] "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print("Failed to save the sync state, the next sync will only be able to rely on the modified dates.\n"));
+		let _ = stdout.flush();
+	}
+
+	completed
+}
+
+//Everything that's going to be uploaded or downloaded, for the bar of the whole sync
+fn get_sync_progress(all_linked_files: &Vec<LinkedFile>) -> SyncProgress
+{
+	let mut total_files: usize = 0;
+	let mut total_bytes: u64 = 0;
+	for linked_file in all_linked_files
+	{
+		let direction = match linked_file.sync_veredict
+		{
+			SyncVeredict::UploadToRemote => TransferDirection::Upload,
+			SyncVeredict::DownloadToLocal => TransferDirection::Download,
+			_ => continue,
+		};
+
+		if is_linked_directory(linked_file) || is_linked_symlink(linked_file)
+		{
+			continue;
+		}
+
+		total_files += 1;
+		total_bytes += get_transfer_size(linked_file, direction);
+	}
+
+	SyncProgress::new(total_files, total_bytes)
+}
+
+fn get_transfer_size(linked_file: &LinkedFile, direction: TransferDirection) -> u64
+{
+	let file = match direction
+	{
+		TransferDirection::Upload => &linked_file.local_file,
+		TransferDirection::Download => &linked_file.remote_file,
+	};
+
+	match file
+	{
+		Some(value) => value.size,
+		None => 0,
+	}
+}
+
+//The older version of the file is renamed on its side to name.conflict-<host>-<date>, where host is the machine the copy comes from, then the plan is changed so the newer version
+//overwrites the original path and the renamed copy is synced to the other side like any other new file.
+fn keep_both_versions(all_linked_files: Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, report: &mut Report, args: &Args) -> (Vec<LinkedFile>, bool)
+{
+	let mut stdout = io::stdout();
+	let mut new_linked_files_list: Vec<LinkedFile> = Vec::with_capacity(all_linked_files.len());
+	let date = Local::now().format("%Y%m%d-%H%M%S");
+	let local_conflict_suffix = format!(".conflict-{}-{}", get_host_name(), date);
+	let mut completed = true;
+	let remote_conflict_suffix = format!(".conflict-{}-{}", get_remote_host_name(&sync_location.remote), date);
+
+	for mut linked_file in all_linked_files
+	{
+		if linked_file.sync_veredict != SyncVeredict::KeepBoth
+		{
+			new_linked_files_list.push(linked_file);
+			continue;
+		}
+
+		//After a failure nothing else is renamed, the rest are left as conflicts
+		if !completed
+		{
+			linked_file.sync_veredict = SyncVeredict::Conflict;
+			new_linked_files_list.push(linked_file);
+			continue;
+		}
+
+		let (local_file, remote_file) = match (&linked_file.local_file, &linked_file.remote_file)
+		{
+			(Some(local_file), Some(remote_file)) => (local_file.clone(), remote_file.clone()),
+			_ =>
+			{
+				new_linked_files_list.push(linked_file);
+				continue;
+			}
+		};
+
+		let local_is_older = local_file.date_modified.timestamp() < remote_file.date_modified.timestamp();
+		let conflict_suffix = if local_is_older { &local_conflict_suffix } else { &remote_conflict_suffix };
+		let conflict_relative_path = format!("{}{}", linked_file.relative_path, conflict_suffix);
+
+		{ //Imprimir bonito
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print("\n!!"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(if local_is_older { " Keeping local copy as: " } else { " Keeping remote copy as: " }));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, Print(&conflict_relative_path));
+			let _ = stdout.flush();
+		}
+
+		let renamed = if local_is_older
+		{
+			//Next to the file, its directory might be written differently than on the remote
+			let conflict_fullpath = format!("{}{}", local_file.fullpath, conflict_suffix);
+			match fs::rename(&local_file.fullpath, &conflict_fullpath)
+			{
+				Ok(_) =>
+				{
+					new_linked_files_list.push
+					(
+						LinkedFile
+						{
+							relative_path: conflict_relative_path.clone(),
+							relative_directory: linked_file.relative_directory.clone(),
+							local_relative_path: None,
+							local_file: Some
+							(
+								File
+								{
+									directory: local_file.directory.clone(),
+									fullpath: conflict_fullpath,
+									relative_path: conflict_relative_path.clone(),
+									date_modified: local_file.date_modified,
+									date_is_exact: local_file.date_is_exact,
+									size: local_file.size,
+									hash: local_file.hash.clone(),
+									is_directory: false,
+									symlink_target: None,
+								}
+							),
+							remote_file: None,
+							sync_veredict: SyncVeredict::UploadToRemote,
+							last_synced: None,
+							conflict_resolution: None,
+						}
+					);
+					linked_file.sync_veredict = SyncVeredict::DownloadToLocal;
+					Ok(())
+				},
+				Err(error) => Err(error.to_string()),
+			}
+		}
+		else
+		{
+			let conflict_fullpath = format!("{}{}", sync_location.remote_path, conflict_relative_path);
+			match retry_remote_command("Failed to rename remote file", sync_location, ftp_stream, server_features, |ftp_stream, _| ftp_stream.rename(&remote_file.fullpath, &conflict_fullpath))
+			{
+				Ok(_) =>
+				{
+					new_linked_files_list.push
+					(
+						LinkedFile
+						{
+							relative_path: conflict_relative_path.clone(),
+							relative_directory: linked_file.relative_directory.clone(),
+							local_relative_path: Some(format!("{}{}", local_file.relative_path, conflict_suffix)),
+							local_file: None,
+							remote_file: Some
+							(
+								File
+								{
+									directory: remote_file.directory.clone(),
+									fullpath: conflict_fullpath,
+									relative_path: conflict_relative_path.clone(),
+									date_modified: remote_file.date_modified,
+									date_is_exact: remote_file.date_is_exact,
+									size: remote_file.size,
+									hash: remote_file.hash.clone(),
+									is_directory: false,
+									symlink_target: None,
+								}
+							),
+							sync_veredict: SyncVeredict::DownloadToLocal,
+							last_synced: None,
+							conflict_resolution: None,
+						}
+					);
+					linked_file.sync_veredict = SyncVeredict::UploadToRemote;
+					Ok(())
+				},
+				Err(error) => Err(error.to_string()),
+			}
+		};
+
+		match renamed
+		{
+			Ok(_) =>
+			{
+				let _ = queue!(stdout, SetForegroundColor(Color::Green));
+				let _ = queue!(stdout, Print(" (done!)"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = stdout.flush();
+			},
+			Err(error) =>
+			{
+				report.errors += 1;
+				let _ = queue!(stdout, SetForegroundColor(Color::Red));
+				let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(format!("Failed to rename the older version of {}, the conflict will be skipped. {}", &linked_file.relative_path, error)));
+				let _ = stdout.flush();
+
+				if !args.continue_on_error
+				{
+					completed = false;
+				}
+				linked_file.sync_veredict = SyncVeredict::Conflict;
+			}
+		}
+
+		new_linked_files_list.push(linked_file);
+	}
+
+	(new_linked_files_list, completed)
+}
+
+//After syncing both sides have the same content as the local file, so its hash is saved to avoid asking the server for it next time.
+//Only files transferred or found equal in this sync are hashed, the rest keep what the previous sync saved, since their local copy
+//may have changed since then.
+fn remember_hashes(all_linked_files: &[LinkedFile], sync_state: &mut SyncState, sync_location: &SyncLocation, server_features: &ServerFeatures, hash_cache: &mut LocalHashCache)
+{
+	let algorithm = get_state_hash_algorithm(server_features);
 
 	for linked_file in all_linked_files
 	{
-		if linked_file.sync_veredict == SyncVeredict::DownloadToLocal
+		match linked_file.sync_veredict
+		{
+			SyncVeredict::UploadToRemote | SyncVeredict::DownloadToLocal | SyncVeredict::DoNothing => (),
+			_ => continue,
+		}
+
+		let synced_file_state = match sync_state.files.get_mut(&linked_file.relative_path)
+		{
+			Some(value) => value,
+			None => continue,
+		};
+
+		if synced_file_state.hash.is_some()
+		{
+			continue;
+		}
+
+		//A transfer that failed left what the previous sync saved
+		if linked_file.sync_veredict != SyncVeredict::DoNothing && linked_file.last_synced.as_ref() == Some(synced_file_state)
+		{
+			continue;
+		}
+
+		let local_size = match synced_file_state.local_size
+		{
+			Some(value) => value,
+			None => continue,
+		};
+
+		let (_, local_fullpath) = get_local_location(linked_file, sync_location);
+		synced_file_state.hash = hash_cache.get_hash(&local_fullpath, local_size, synced_file_state.local_date_modified, algorithm);
+	}
+}
+
+fn get_host_name() -> String
+{
+	let host_name = match env::var("COMPUTERNAME").or_else(|_| env::var("HOSTNAME"))
+	{
+		Ok(value) => value,
+		Err(_) =>
+		{
+			match fs::read_to_string("/etc/hostname")
+			{
+				Ok(value) => value,
+				Err(_) => String::from("unknown"),
+			}
+		}
+	};
+
+	to_file_name_part(&host_name)
+}
+
+fn get_remote_host_name(remote: &str) -> String
+{
+	//Without the port
+	let host_name = match remote.rsplit_once(':')
+	{
+		Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+		_ => remote,
+	};
+
+	to_file_name_part(host_name.trim_start_matches('[').trim_end_matches(']'))
+}
+
+//It ends up in a file name, so keep it simple
+fn to_file_name_part(host_name: &str) -> String
+{
+	let host_name: String = host_name.trim().chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+	if host_name.is_empty()
+	{
+		String::from("unknown")
+	}
+	else
+	{
+		host_name
+	}
+}
+
+//Reads the date of a single remote file the same way get_all_remote_files_recursive_from does, so it can be compared with it in the next sync.
+fn get_remote_date_modified(remote_fullpath: &str, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures) -> Option<DateTime<Utc>>
+{
+	if let Some(value) = ftp::get_remote_date_modified_exact(ftp_stream, server_features, remote_fullpath)
+	{
+		return Some(value);
+	}
+
+	let listing = match ftp_stream.list(Some(remote_fullpath))
+	{
+		Ok(value) => value,
+		Err(_) => return None,
+	};
+
+	for item in listing
+	{
+		match list::File::from_str(item.as_str())
+		{
+			Ok(value) =>
+			{
+				if value.is_file()
+				{
+					return Some(ftp::get_list_date_modified(&value, server_features));
+				}
+			},
+			Err(_) => continue,
+		}
+	}
+
+	None
+}
+
+//Reads the date of a single local file the same way get_all_local_files_recursive_from does, so it can be compared with it in the next sync.
+fn get_local_date_modified(local_fullpath: &String) -> Option<DateTime<Utc>>
+{
+	let modified = match fs::metadata(local_fullpath)
+	{
+		Ok(metadata) =>
+		{
+			match metadata.modified()
+			{
+				Ok(value) => value,
+				Err(_) => return None,
+			}
+		},
+		Err(_) => return None,
+	};
+
+	let date_modified: DateTime<Utc> = modified.into();
+	date_modified.with_nanosecond(0)
+}
+
+fn print_failed_to_remember_sync<W: Write>(relative_path: &String, stdout: &mut W, args: &Args)
+{
+	if args.log_level == LogLevel::Verbose
+	{
+		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+		let _ = queue!(stdout, Print("\n[WARN] "));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!("Failed to read back the modified date of {relative_path}, it will be treated as never synced the next time.")));
+		let _ = stdout.flush();
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TransferDirection
+{
+	Upload,
+	Download,
+}
+
+enum TransferOutcome
+{
+	Finished, //Transferred or failed for good, either way it's already in the report
+	Interrupted(String), //Failed in a way worth trying again, it isn't in the report yet
+}
+
+//Why a transfer failed, transient failures are the ones worth trying again on a new connection
+struct TransferError
+{
+	message: String,
+	transient: bool,
+	changed: bool, //The file changed since it was listed, so it's left for the next sync instead of trying again
+}
+
+impl TransferError
+{
+	fn new(message: String) -> Self
+	{
+		Self
+		{
+			message,
+			transient: false,
+			changed: false,
+		}
+	}
+
+	fn changed(message: String) -> Self
+	{
+		Self
+		{
+			message,
+			transient: false,
+			changed: true,
+		}
+	}
+
+	fn transient(message: String) -> Self
+	{
+		Self
+		{
+			message,
+			transient: true,
+			changed: false,
+		}
+	}
+
+	fn from_ftp(message: String, error: &FtpError) -> Self
+	{
+		Self
+		{
+			message,
+			transient: ftp::is_transient_error(error),
+			changed: false,
+		}
+	}
+
+	fn from_io(message: String, error: &io::Error) -> Self
+	{
+		Self
+		{
+			message,
+			transient: ftp::is_transient_io_error(error),
+			changed: false,
+		}
+	}
+}
+
+//With more than one connection, each one takes the next file from the list until there's none left. The first connection is the one
+//used for everything else, the rest are opened only for this.
+//Returns false when a failed transfer stopped the rest, once every connection finished the file it was on.
+#[allow(clippy::too_many_arguments)]
+fn transfer_files(files: &Vec<&LinkedFile>, direction: TransferDirection, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, transfer_limits: &TransferLimits, progress: &SyncProgress, args: &Args) -> bool
+{
+	let connections = sync_location.connections.min(files.len());
+	if connections <= 1
+	{
+		let mut stdout = progress.output();
+		for linked_file in files
+		{
+			let errors = report.errors;
+			transfer_file(linked_file, direction, sync_location, ftp_stream, server_features, sync_state, report, transfer_limits, progress, &mut stdout, args);
+			if report.errors > errors && !args.continue_on_error
+			{
+				let _ = stdout.flush();
+				progress.finish();
+				return false;
+			}
+		}
+		let _ = stdout.flush();
+		progress.finish();
+		return true;
+	}
+
+	let next_file = AtomicUsize::new(0);
+	let aborted = AtomicBool::new(false);
+	let shared_sync_state = Mutex::new(sync_state);
+	let shared_report = Mutex::new(report);
+	let extra_server_features = server_features.clone();
+
+	thread::scope(|scope|
+	{
+		for _ in 1..connections
+		{
+			let next_file = &next_file;
+			let aborted = &aborted;
+			let shared_sync_state = &shared_sync_state;
+			let shared_report = &shared_report;
+			let mut server_features = extra_server_features.clone();
+			scope.spawn(move ||
+			{
+				let mut ftp_stream = match ftp::open_connection(sync_location, &server_features)
+				{
+					Ok(value) => value,
+					Err(error) =>
+					{
+						//The other connections take its share
+						let mut stdout = progress.output();
+						let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+						let _ = queue!(stdout, Print("\n[WARN] "));
+						let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+						let _ = queue!(stdout, Print(format!("Failed to open an extra connection, {error}")));
+						let _ = stdout.flush();
+						return;
+					}
+				};
+				transfer_files_from_queue(files, next_file, aborted, direction, sync_location, &mut ftp_stream, &mut server_features, shared_sync_state, shared_report, transfer_limits, progress, args);
+				let _ = ftp_stream.quit();
+			});
+		}
+
+		transfer_files_from_queue(files, &next_file, &aborted, direction, sync_location, ftp_stream, server_features, &shared_sync_state, &shared_report, transfer_limits, progress, args);
+	});
+	progress.finish();
+
+	!aborted.load(Ordering::SeqCst)
+}
+
+//Files already being transferred by other connections are finished when one fails, only new ones stop being taken
+#[allow(clippy::too_many_arguments)]
+fn transfer_files_from_queue(files: &Vec<&LinkedFile>, next_file: &AtomicUsize, aborted: &AtomicBool, direction: TransferDirection, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, shared_sync_state: &Mutex<&mut SyncState>, shared_report: &Mutex<&mut Report>, transfer_limits: &TransferLimits, progress: &SyncProgress, args: &Args)
+{
+	loop
+	{
+		if aborted.load(Ordering::SeqCst)
+		{
+			return;
+		}
+
+		let linked_file = match files.get(next_file.fetch_add(1, Ordering::SeqCst))
+		{
+			Some(value) => *value,
+			None => return,
+		};
+
+		//Each file is synced on its own state and report, then they're put together with everyone else's
+		let mut sync_state = SyncState::new();
+		if let Some(value) = lock(shared_sync_state).files.get(&linked_file.relative_path)
+		{
+			sync_state.files.insert(linked_file.relative_path.clone(), value.clone());
+		}
+		let mut report = Report::new();
+
+		//Printed all at once when the file is done, so the lines of files transferred at the same time never get mixed
+		let mut output: Vec<u8> = Vec::new();
+		transfer_file(linked_file, direction, sync_location, ftp_stream, server_features, &mut sync_state, &mut report, transfer_limits, progress, &mut output, args);
+		progress.print(&output);
+
+		match sync_state.files.remove(&linked_file.relative_path)
+		{
+			Some(value) =>
+			{
+				lock(shared_sync_state).files.insert(linked_file.relative_path.clone(), value);
+			},
+			None =>
+			{
+				lock(shared_sync_state).files.remove(&linked_file.relative_path);
+			}
+		}
+
+		let failed = report.errors > 0;
+		lock(shared_report).add(report);
+		if failed && !args.continue_on_error
+		{
+			aborted.store(true, Ordering::SeqCst);
+			return;
+		}
+	}
+}
+
+//A connection that panicked while holding the lock leaves the mutex poisoned. Its file was left unsynced and the state and
+//report inside are still whole, so the other connections keep using them instead of panicking too.
+fn lock<'a, T>(mutex: &'a Mutex<T>) -> MutexGuard<'a, T>
+{
+	match mutex.lock()
+	{
+		Ok(value) => value,
+		Err(poisoned) => poisoned.into_inner(),
+	}
+}
+
+//A transfer interrupted by the connection is tried again on a new one, it continues from where it stopped thanks to the temporary file
+#[allow(clippy::too_many_arguments)]
+fn transfer_file<W: Write>(linked_file: &LinkedFile, direction: TransferDirection, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, transfer_limits: &TransferLimits, progress: &SyncProgress, stdout: &mut W, args: &Args)
+{
+	let mut attempt: u32 = 0;
+	loop
+	{
+		let can_retry = attempt < sync_location.retries;
+		let outcome = match direction
+		{
+			TransferDirection::Upload => upload_file(linked_file, sync_location, ftp_stream, server_features, sync_state, report, transfer_limits.upload.as_ref(), progress, can_retry, stdout, args),
+			TransferDirection::Download => download_file(linked_file, sync_location, ftp_stream, server_features, sync_state, report, transfer_limits.download.as_ref(), progress, can_retry, stdout, args),
+		};
+
+		let error = match outcome
+		{
+			TransferOutcome::Finished =>
+			{
+				progress.finish_file(get_transfer_size(linked_file, direction));
+				return;
+			},
+			TransferOutcome::Interrupted(error) => error,
+		};
+
+		attempt += 1;
+		{ //Imprimir bonito
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print(" (interrupted!)"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = stdout.flush();
+		}
+		wait_and_reconnect(&error, attempt, sync_location, ftp_stream, server_features, stdout);
+	}
+}
+
+//For the commands that aren't listings or transfers, which are tried again on a new connection the same way when it's lost.
+//The connection might have dropped after the server did what was asked, so trying again can fail even if it was done.
+fn retry_remote_command<T, F>(description: &str, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, mut command: F) -> Result<T, FtpError>
+where F: FnMut(&mut FtpStream, &mut ServerFeatures) -> Result<T, FtpError>
+{
+	let mut attempt: u32 = 0;
+	loop
+	{
+		let error = match command(ftp_stream, server_features)
+		{
+			Ok(value) => return Ok(value),
+			Err(error) => error,
+		};
+
+		if !ftp::is_transient_error(&error) || attempt >= sync_location.retries
+		{
+			return Err(error);
+		}
+
+		attempt += 1;
+		let mut stdout = io::stdout();
+		wait_and_reconnect(&format!("{description} ({error})"), attempt, sync_location, ftp_stream, server_features, &mut stdout);
+	}
+}
+
+//Waits twice as long on every attempt, so a server that's restarting or a network that's coming back has time to do it
+fn get_retry_delay(attempt: u32) -> Duration
+{
+	Duration::from_secs(2u64.saturating_pow(attempt.saturating_sub(1)).min(MAX_RETRY_DELAY))
+}
+
+//The connection is always replaced, asking if the old one still works could hang if it's half open
+fn wait_and_reconnect<W: Write>(error: &str, attempt: u32, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &ServerFeatures, stdout: &mut W)
+{
+	let delay = get_retry_delay(attempt);
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+		let _ = queue!(stdout, Print("\n[WARN] "));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!("{error}\nTrying again in {} second{} (attempt {attempt} of {})...", delay.as_secs(), if delay.as_secs() == 1 { "" } else { "s" }, sync_location.retries)));
+		let _ = stdout.flush();
+	}
+	thread::sleep(delay);
+
+	match ftp::reconnect(ftp_stream, sync_location, server_features)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			//The next attempt fails right away and waits longer before reconnecting again
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("\n[WARN] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to reconnect, {error}")));
+			let _ = stdout.flush();
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn upload_to_remote(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, transfer_limits: &TransferLimits, progress: &SyncProgress, args: &Args) -> bool
+{
+	//Directories are created first and one at a time, so every file already has where to go when they're transferred in parallel
+	let mut files: Vec<&LinkedFile> = Vec::new();
+	for linked_file in get_ordered_for_sync(all_linked_files, SyncVeredict::UploadToRemote)
+	{
+		if is_linked_directory(linked_file)
+		{
+			if !create_remote_directory(linked_file, sync_location, ftp_stream, server_features, sync_state, report, args)
+			{
+				return false;
+			}
+			continue;
+		}
+
+		if is_linked_symlink(linked_file)
+		{
+			if !create_remote_symlink(linked_file, sync_location, ftp_stream, server_features, sync_state, report, args)
+			{
+				return false;
+			}
+			continue;
+		}
+
+		files.push(linked_file);
+	}
+
+	transfer_files(&files, TransferDirection::Upload, sync_location, ftp_stream, server_features, sync_state, report, transfer_limits, progress, args)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn upload_file<W: Write>(linked_file: &LinkedFile, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, limiter: Option<&RateLimiter>, progress: &SyncProgress, can_retry: bool, stdout: &mut W, args: &Args) -> TransferOutcome
+{
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print("\n->"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Uploading: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	let remote_directory = format!("{}{}", sync_location.remote_path, linked_file.relative_directory);
+	let remote_fullpath = format!("{}{}", sync_location.remote_path, linked_file.relative_path);
+	//println!("{remote_directory}");
+
+	//Comprobar que el directorio existe
+	let directory_listing = ftp_stream.list(Some(remote_directory.as_str())).ok();
+	let directory_exists = directory_listing.is_some();
+
+	//Crear directorio si no existe
+	if !directory_exists
+	{
+		match ftp_stream.mkdir(&remote_directory)
+		{
+			Ok(_) => 
+			{
+				if args.log_level == LogLevel::Verbose
+				{
+					let _ = queue!(stdout, SetForegroundColor(Color::Cyan));
+					let _ = queue!(stdout, Print("\n[INFO] "));
+					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+					let _ = queue!(stdout, Print(format!("Directory {} created on remote.\n", &remote_directory).as_str()));
+					let _ = stdout.flush();
+				}
+			},
+			Err(error) =>
+			{
+				if can_retry && ftp::is_transient_error(&error)
+				{
+					return TransferOutcome::Interrupted(format!("Failed to create remote directory ({}), {}", &remote_directory, error));
+				}
+
+				report.errors += 1;
+				let _ = queue!(stdout, SetForegroundColor(Color::Red));
+				let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(format!("Failed to create remote directory ({}), {}\n", &remote_directory, error)));
+				let _ = stdout.flush();
+				return TransferOutcome::Finished;
+			}
+		}
+	}
+
+	//Cargar archivo local
+	let local_file = match &linked_file.local_file
+	{
+		Some(value) => value,
+		None =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to access internal local file handler! ({})\n", &remote_directory)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	};
+
+	let mut local_file_handler = match fs::File::open(local_file.fullpath.clone())
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to open local file!! ({}) {}\n", &remote_directory, error)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	};
+
+	//Subir archivo a remote
+	//It goes to a hidden name first, so other devices never see (and download) a file that's still being uploaded
+	let temporary_fullpath = get_temporary_path(&remote_fullpath, local_file);
+	if let Some(listing) = &directory_listing
+	{
+		remove_stale_remote_temporary_files(listing, &remote_directory, &temporary_fullpath, ftp_stream);
+	}
+
+	//The temporary file is kept if the upload fails, so the next attempt can continue from where this one stopped
+	let file_progress = progress.start_file(&linked_file.relative_path, local_file.size, limiter);
+	let uploaded = match upload_to_temporary_file(&mut local_file_handler, local_file.size, &temporary_fullpath, ftp_stream, limiter, &file_progress)
+	{
+		Ok(uploaded_size) => verify_transfer(&local_file.fullpath, &temporary_fullpath, uploaded_size, ftp_stream, server_features),
+		Err(error) => Err(error),
+	};
+	drop(file_progress);
+
+	match uploaded
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			if can_retry && error.transient
+			{
+				return TransferOutcome::Interrupted(format!("Failed to upload file to remote ({}), {}", &remote_fullpath, error.message));
+			}
+
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to upload file to remote ({})\n{}\n", &remote_fullpath, error.message)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	}
+
+	match replace_remote_file(&temporary_fullpath, &remote_fullpath, ftp_stream)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			//The remote file might be gone now, it must not look like it was deleted on purpose the next time
+			sync_state.files.remove(&linked_file.relative_path);
+			if can_retry && error.transient
+			{
+				return TransferOutcome::Interrupted(format!("Failed to move the uploaded file into place ({}), {}", &remote_fullpath, error.message));
+			}
+
+			//The temporary file is kept, the old file might be gone already and the next sync uploads from it
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to move the uploaded file into place ({})\n{}\n", &remote_fullpath, error.message)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	}
+
+	//Give the remote copy the same modified date, otherwise the next sync would see it as newer and download it back
+	if !ftp::set_remote_date_modified(ftp_stream, server_features, &remote_fullpath, &local_file.date_modified)
+	&& args.log_level == LogLevel::Verbose
+	{
+		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+		let _ = queue!(stdout, Print("\n[WARN] "));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(format!("The server didn't allow to set the modified date of {}.", &remote_fullpath)));
+		let _ = stdout.flush();
+	}
+
+	//Remember how both sides were left
+	match get_remote_date_modified(&remote_fullpath, ftp_stream, server_features)
+	{
+		Some(remote_date_modified) =>
+		{
+			sync_state.files.insert
+			(
+				linked_file.relative_path.clone(),
+				SyncedFileState
+				{
+					local_date_modified: local_file.date_modified.timestamp(),
+					remote_date_modified: remote_date_modified.timestamp(),
+					local_size: Some(local_file.size),
+					remote_size: match ftp_stream.size(&remote_fullpath)
+					{
+						Ok(value) => Some(value as u64),
+						Err(_) => None,
+					},
+					hash: None,
+				}
+			);
+		},
+		None =>
+		{
+			sync_state.files.remove(&linked_file.relative_path);
+			print_failed_to_remember_sync(&linked_file.relative_path, stdout, args);
+		}
+	}
+
+	report.uploaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+	TransferOutcome::Finished
+}
+
+//Returns how big the uploaded file should be, counting what was already there from an interrupted upload
+fn upload_to_temporary_file(local_file_handler: &mut fs::File, local_size: u64, temporary_fullpath: &String, ftp_stream: &mut FtpStream, limiter: Option<&RateLimiter>, file_progress: &FileProgress) -> Result<u64, TransferError>
+{
+	//Part of this same version of the file might already be on the server from an interrupted upload
+	let resume_from = match ftp_stream.size(temporary_fullpath)
+	{
+		Ok(value) if (value as u64) < local_size => value as u64,
+		_ => 0,
+	};
+
+	if resume_from > 0
+	{
+		match local_file_handler.seek(SeekFrom::Start(resume_from))
+		{
+			Ok(_) => (),
+			Err(error) => return Err(TransferError::new(error.to_string())),
+		}
+
+		file_progress.set_position(resume_from);
+		match ftp_stream.append_file(temporary_fullpath, &mut LimitedReader::new(ProgressReader::new(&mut *local_file_handler, file_progress), limiter))
+		{
+			Ok(value) => return Ok(resume_from + value),
+			Err(error) =>
+			{
+				//Without APPE the upload starts again from the beginning
+				if !ftp::is_not_implemented(&error)
+				{
+					return Err(TransferError::from_ftp(error.to_string(), &error));
+				}
+			}
+		}
+
+		match local_file_handler.seek(SeekFrom::Start(0))
+		{
+			Ok(_) => (),
+			Err(error) => return Err(TransferError::new(error.to_string())),
+		}
+		file_progress.set_position(0);
+	}
+
+	match ftp_stream.put_file(temporary_fullpath, &mut LimitedReader::new(ProgressReader::new(local_file_handler, file_progress), limiter))
+	{
+		Ok(value) => Ok(value),
+		Err(error) => Err(TransferError::from_ftp(error.to_string(), &error)),
+	}
+}
+
+//Both copies must have as many bytes as were transferred, and the same hash when the server can hash files. Mismatches are worth trying
+//again, most likely the connection dropped or one of the files changed during the transfer.
+fn verify_transfer(local_fullpath: &String, remote_fullpath: &String, transferred_size: u64, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures) -> Result<(), TransferError>
+{
+	match fs::metadata(local_fullpath)
+	{
+		Ok(metadata) =>
+		{
+			if metadata.len() != transferred_size
+			{
+				return Err(TransferError::transient(format!("the local file has {} bytes but {transferred_size} were transferred", metadata.len())));
+			}
+		},
+		Err(error) => return Err(TransferError::new(format!("failed to check the size of the local file, {error}"))),
+	}
+
+	//Servers without SIZE can't be checked, the transfer is trusted like before
+	match ftp_stream.size(remote_fullpath)
+	{
+		Ok(value) =>
+		{
+			if value as u64 != transferred_size
+			{
+				return Err(TransferError::transient(format!("the file on the server has {value} bytes but {transferred_size} were transferred")));
+			}
+		},
+		Err(error) =>
+		{
+			if !ftp::is_not_implemented(&error)
+			{
+				return Err(TransferError::from_ftp(format!("failed to check the size of the file on the server, {error}"), &error));
+			}
+		}
+	}
+
+	let remote_hash = match ftp::get_remote_hash(ftp_stream, server_features, remote_fullpath)
+	{
+		Some(value) => value,
+		None => return Ok(()),
+	};
+	let algorithm = match HashAlgorithm::from_tagged_hash(&remote_hash)
+	{
+		Some(value) => value,
+		None => return Ok(()),
+	};
+
+	match hash::hash_local_file(local_fullpath, algorithm)
+	{
+		Some(local_hash) =>
+		{
+			if local_hash != remote_hash
+			{
+				return Err(TransferError::transient(format!("the {} hash of the file on the server doesn't match the local one", algorithm.name())));
+			}
+		},
+		None => return Err(TransferError::new(String::from("failed to hash the local file"))),
+	}
+
+	Ok(())
+}
+
+//Some servers refuse to rename over an existing file, for those the old file is deleted first. That's only done when the rename failed
+//because the file is there, if it failed for anything else deleting it would lose the old version for nothing.
+//The temporary file is never deleted here, if it can't be moved into place it's the only copy left.
+fn replace_remote_file(temporary_fullpath: &String, remote_fullpath: &String, ftp_stream: &mut FtpStream) -> Result<(), TransferError>
+{
+	let error = match ftp_stream.rename(temporary_fullpath, remote_fullpath)
+	{
+		Ok(_) => return Ok(()),
+		Err(error) => error,
+	};
+
+	//Servers without RNFR and RNTO won't do it the second time either
+	if ftp::is_transient_error(&error) || ftp::is_not_implemented(&error) || ftp::remote_file_exists(ftp_stream, remote_fullpath) != Some(true)
+	{
+		return Err(TransferError::from_ftp(error.to_string(), &error));
+	}
+
+	match ftp_stream.rm(remote_fullpath)
+	{
+		Ok(_) => (),
+		Err(rm_error) => return Err(TransferError::from_ftp(format!("{error}, and the old file couldn't be deleted to make room, {rm_error}"), &rm_error)),
+	}
+
+	match ftp_stream.rename(temporary_fullpath, remote_fullpath)
+	{
+		Ok(_) => Ok(()),
+		Err(error) => Err(TransferError::from_ftp(format!("the old file was deleted but the new one couldn't take its place, it's kept as {temporary_fullpath}, {error}"), &error)),
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn download_to_local(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, transfer_limits: &TransferLimits, progress: &SyncProgress, args: &Args) -> bool
+{
+	let mut files: Vec<&LinkedFile> = Vec::new();
+	for linked_file in get_ordered_for_sync(all_linked_files, SyncVeredict::DownloadToLocal)
+	{
+		if is_linked_directory(linked_file)
+		{
+			if !create_local_directory(linked_file, sync_location, sync_state, report, args)
+			{
+				return false;
+			}
+			continue;
+		}
+
+		if is_linked_symlink(linked_file)
+		{
+			if !create_local_symlink(linked_file, sync_location, sync_state, report, args)
+			{
+				return false;
+			}
+			continue;
+		}
+
+		files.push(linked_file);
+	}
+
+	transfer_files(&files, TransferDirection::Download, sync_location, ftp_stream, server_features, sync_state, report, transfer_limits, progress, args)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn download_file<W: Write>(linked_file: &LinkedFile, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, limiter: Option<&RateLimiter>, progress: &SyncProgress, can_retry: bool, stdout: &mut W, args: &Args) -> TransferOutcome
+{
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Blue));
+		let _ = queue!(stdout, Print("\n<-"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Downloading: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	//The local file might be named differently, it's the one that gets replaced
+	let (local_directory, local_fullpath) = get_local_location(linked_file, sync_location);
+	//println!("{remote_directory}");
+
+	//Comprobar que el directorio existe
+	let directory_exists: bool = list_local_directory(&local_directory).is_some();
+
+	//Crear directorio si no existe
+	if !directory_exists
+	{
+		match fs::create_dir(local_directory.as_str())
 		{
-			{ //Imprimir bonito
-				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-				let _ = queue!(stdout, SetForegroundColor(Color::Blue));
-				let _ = queue!(stdout, Print("\n<-"));
+			Ok(_) =>
+			{
+				if args.log_level == LogLevel::Verbose
+				{
+					let _ = queue!(stdout, SetForegroundColor(Color::Cyan));
+					let _ = queue!(stdout, Print("\n[INFO] "));
+					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+					let _ = queue!(stdout, Print(format!("Directory {} created on local machine.\n", &local_directory).as_str()));
+					let _ = stdout.flush();
+				}
+			},
+			Err(error) =>
+			{
+				report.errors += 1;
+				let _ = queue!(stdout, SetForegroundColor(Color::Red));
+				let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
 				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-				let _ = queue!(stdout, Print(" Downloading: "));
-				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-				let _ = queue!(stdout, Print(format!("{}", linked_file.relative_path)));
+				let _ = queue!(stdout, Print(format!("Failed to create directory ({}), {}", &local_directory, error)));
 				let _ = stdout.flush();
+				return TransferOutcome::Finished;
 			}
+		}
+	}
+
+	//Cargar archivo remote
+	let remote_file = match &linked_file.remote_file
+	{
+		Some(value) => value,
+		None =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to access internal remote file handler! ({})", &linked_file.relative_path)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	};
 
-			let local_directory = format!("{}{}", sync_location.local_path, linked_file.relative_directory);
-			let local_fullpath = format!("{}{}", sync_location.local_path, linked_file.relative_path);
-			//println!("{remote_directory}");
+	//Download next to the file and only replace it once the download is complete, so an interrupted download never leaves a broken file behind
+	let temporary_fullpath = get_temporary_path(&local_fullpath, remote_file);
+	remove_stale_local_temporary_files(&local_directory, &temporary_fullpath);
 
-			//Comprobar que el directorio existe
-			let directory_exists: bool = match list_local_directory(&local_directory)
+	//The temporary file is kept if the download fails, so the next attempt can continue from where this one stopped
+	let file_progress = progress.start_file(&linked_file.relative_path, remote_file.size, limiter);
+	let downloaded = match download_to_temporary_file(remote_file, &temporary_fullpath, ftp_stream, limiter, &file_progress)
+	{
+		Ok((value, downloaded_size)) => match check_downloaded_size(remote_file, downloaded_size, ftp_stream)
+		{
+			Ok(_) => match verify_transfer(&temporary_fullpath, &remote_file.fullpath, downloaded_size, ftp_stream, server_features)
 			{
-				Some(_) => true,
-				None => false,
-			};
+				Ok(_) => Ok(value),
+				Err(error) => Err(error),
+			},
+			Err(error) => Err(error),
+		},
+		Err(error) => Err(error),
+	};
+	drop(file_progress);
+
+	let local_file_handler = match downloaded
+	{
+		Ok(value) => value,
+		Err(error) =>
+		{
+			//The state isn't touched, so the next sync compares it again with what's on the server by then
+			if error.changed
+			{
+				let _ = fs::remove_file(&temporary_fullpath);
+				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+				let _ = queue!(stdout, Print(" (skipped!)"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = stdout.flush();
+				report.skipped.push((linked_file.relative_path.clone(), error.message));
+				return TransferOutcome::Finished;
+			}
+
+			if can_retry && error.transient
+			{
+				return TransferOutcome::Interrupted(format!("Failed to download file ({}), {}", &linked_file.relative_path, error.message));
+			}
 
-			//Crear directorio si no existe
-			if !directory_exists
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to download file ({}), {}", &linked_file.relative_path, error.message)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	};
+
+	//Give the local copy the same modified date, otherwise the next sync would see it as newer and upload it back
+	match local_file_handler.set_modified(SystemTime::from(remote_file.date_modified))
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			if args.log_level == LogLevel::Verbose
 			{
-				match fs::create_dir(&local_directory.as_str())
+				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+				let _ = queue!(stdout, Print("\n[WARN] "));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(format!("Failed to set the modified date of {}, {}", &local_fullpath, error)));
+				let _ = stdout.flush();
+			}
+		}
+	}
+
+	drop(local_file_handler);
+
+	match fs::rename(&temporary_fullpath, &local_fullpath)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			let _ = fs::remove_file(&temporary_fullpath);
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to replace local file with the downloaded one ({}), {}", &local_fullpath, error)));
+			let _ = stdout.flush();
+			return TransferOutcome::Finished;
+		}
+	}
+
+	//Remember how both sides were left
+	match get_local_date_modified(&local_fullpath)
+	{
+		Some(local_date_modified) =>
+		{
+			sync_state.files.insert
+			(
+				linked_file.relative_path.clone(),
+				SyncedFileState
 				{
-					Ok(_) =>
+					local_date_modified: local_date_modified.timestamp(),
+					remote_date_modified: remote_file.date_modified.timestamp(),
+					local_size: match fs::metadata(&local_fullpath)
 					{
-						if args.log_level == LogLevel::Verbose
-						{
-							let _ = queue!(stdout, SetForegroundColor(Color::Cyan));
-							let _ = queue!(stdout, Print("\n[INFO] "));
-							let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-							let _ = queue!(stdout, Print(format!("Directory {} created on local machine.\n", &local_directory).as_str()));
-							let _ = stdout.flush();
-						}
+						Ok(metadata) => Some(metadata.len()),
+						Err(_) => None,
 					},
-					Err(error) =>
-					{
-						report.errors += 1;
-						let _ = queue!(stdout, SetForegroundColor(Color::Red));
-						let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-						let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-						let _ = queue!(stdout, Print(format!("Failed to create directory ({}), {}", &local_directory, error)));
-						let _ = stdout.flush();
-
-						if !args.continue_on_error
-						{
-							std::process::exit(1);
-						}
-						continue;
-					}
+					remote_size: Some(remote_file.size),
+					hash: None,
 				}
+			);
+		},
+		None =>
+		{
+			sync_state.files.remove(&linked_file.relative_path);
+			print_failed_to_remember_sync(&linked_file.relative_path, stdout, args);
+		}
+	}
+
+	report.downloaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+	TransferOutcome::Finished
+}
+
+//A download that doesn't match the size from the listing is either incomplete or the file was replaced on the server after it was listed, SIZE tells which one.
+fn check_downloaded_size(remote_file: &File, downloaded_size: u64, ftp_stream: &mut FtpStream) -> Result<(), TransferError>
+{
+	if downloaded_size == remote_file.size
+	{
+		return Ok(());
+	}
+
+	match ftp_stream.size(&remote_file.fullpath)
+	{
+		Ok(value) if value as u64 == downloaded_size => Err(TransferError::changed(String::from("it changed on the server after it was listed, it'll be synced next time"))),
+		_ => Err(TransferError::transient(format!("the download is incomplete, got {downloaded_size} bytes out of {}", remote_file.size))),
+	}
+}
+
+//Writes the whole remote file into the temporary file and makes sure it's complete and on disk, the temporary file is left to the caller to rename or remove.
+fn download_to_temporary_file(remote_file: &File, temporary_fullpath: &String, ftp_stream: &mut FtpStream, limiter: Option<&RateLimiter>, file_progress: &FileProgress) -> Result<(fs::File, u64), TransferError>
+{
+	//Part of this same version of the file might already be here from an interrupted download. Dates from LIST only have minutes, so
+	//a version saved in the same minute with the same size would get the same temporary file, those are downloaded from the beginning.
+	let resume_from = match fs::metadata(temporary_fullpath)
+	{
+		Ok(metadata) if remote_file.date_is_exact && metadata.len() < remote_file.size => metadata.len(),
+		_ => 0,
+	};
+
+	let mut local_file_handler = match fs::OpenOptions::new().write(true).create(true).truncate(resume_from == 0).open(temporary_fullpath)
+	{
+		Ok(value) => value,
+		Err(error) => return Err(TransferError::new(format!("failed to create temporary file {temporary_fullpath}, {error}"))),
+	};
+
+	let resume_from = if resume_from > 0
+	{
+		match ftp_stream.resume_transfer(resume_from as usize)
+		{
+			Ok(_) => resume_from,
+			Err(_) => 0, //Without REST the download starts again from the beginning
+		}
+	}
+	else
+	{
+		0
+	};
+
+	match local_file_handler.set_len(resume_from)
+	{
+		Ok(_) => (),
+		Err(error) => return Err(TransferError::new(format!("failed to prepare temporary file {temporary_fullpath}, {error}"))),
+	}
+	match local_file_handler.seek(SeekFrom::Start(resume_from))
+	{
+		Ok(_) => (),
+		Err(error) => return Err(TransferError::new(format!("failed to prepare temporary file {temporary_fullpath}, {error}"))),
+	}
+
+	let mut remote_file_handler = match ftp_stream.retr_as_stream(&remote_file.fullpath)
+	{
+		Ok(value) => value,
+		Err(error) => return Err(TransferError::from_ftp(format!("unable to retrieve remote file, {error}"), &error)),
+	};
+
+	file_progress.set_position(resume_from);
+	let downloaded_size = match io::copy(&mut remote_file_handler, &mut LimitedWriter::new(ProgressWriter::new(&mut local_file_handler, file_progress), limiter))
+	{
+		Ok(value) => resume_from + value,
+		Err(error) =>
+		{
+			let _ = ftp_stream.finalize_retr_stream(remote_file_handler);
+			return Err(TransferError::from_io(error.to_string(), &error));
+		}
+	};
+
+	match ftp_stream.finalize_retr_stream(remote_file_handler)
+	{
+		Ok(_) => (),
+		Err(error) => return Err(TransferError::from_ftp(format!("failed to finalize remote stream, {error}"), &error)),
+	}
+
+	match local_file_handler.sync_all()
+	{
+		Ok(_) => (),
+		Err(error) => return Err(TransferError::new(format!("failed to write temporary file to disk, {error}"))),
+	}
+
+	Ok((local_file_handler, downloaded_size))
+}
+
+//Temporary files are hidden and live next to the file they're going to replace, so renaming them over it never has to move data between disks.
+//Their name carries the size and date (in seconds) of the file being copied, so an interrupted transfer is only resumed if that file didn't change since then.
+fn get_temporary_path(fullpath: &String, source_file: &File) -> String
+{
+	let fingerprint = format!("{}-{}", source_file.size, source_file.date_modified.timestamp());
+	match fullpath.rsplit_once('/')
+	{
+		Some((directory, name)) => format!("{directory}/.{name}.{fingerprint}{TEMPORARY_FILE_SUFFIX}"),
+		None => format!(".{fullpath}.{fingerprint}{TEMPORARY_FILE_SUFFIX}"),
+	}
+}
+
+fn get_file_name(fullpath: &str) -> &str
+{
+	match fullpath.rsplit_once('/')
+	{
+		Some((_, name)) => name,
+		None => fullpath,
+	}
+}
+
+//True if both are temporary files made for the same file, no matter the version
+fn is_temporary_file_of_same_file(temporary_name: &str, other_temporary_name: &str) -> bool
+{
+	match (get_temporary_file_target(temporary_name), get_temporary_file_target(other_temporary_name))
+	{
+		(Some(a), Some(b)) => a == b,
+		_ => false,
+	}
+}
+
+//".name.<size>-<date>.sync-remote-tmp" returns "name"
+fn get_temporary_file_target(temporary_name: &str) -> Option<&str>
+{
+	let name = temporary_name.strip_prefix('.')?;
+	let name = name.strip_suffix(TEMPORARY_FILE_SUFFIX)?;
+	let (name, fingerprint) = name.rsplit_once('.')?;
+
+	match fingerprint.split_once('-')
+	{
+		Some((size, date_modified)) if size.parse::<u64>().is_ok() && date_modified.parse::<i64>().is_ok() => Some(name),
+		_ => None,
+	}
+}
+
+//Temporary files of older versions of the file can't be resumed anymore, so they're just in the way
+fn remove_stale_local_temporary_files(local_directory: &String, temporary_fullpath: &str)
+{
+	let temporary_name = get_file_name(temporary_fullpath);
+
+	let entries = match fs::read_dir(local_directory)
+	{
+		Ok(value) => value,
+		Err(_) => return,
+	};
+
+	for entry in entries
+	{
+		let entry = match entry
+		{
+			Ok(value) => value,
+			Err(_) => continue,
+		};
+
+		let entry_name = entry.file_name().to_string_lossy().to_string();
+		if entry_name != temporary_name && is_temporary_file_of_same_file(&entry_name, temporary_name)
+		{
+			let _ = fs::remove_file(entry.path());
+		}
+	}
+}
+
+//Same as remove_stale_local_temporary_files, using the listing made to check if the directory exists. Servers that hide dotfiles from LIST keep them.
+fn remove_stale_remote_temporary_files(directory_listing: &Vec<String>, remote_directory: &str, temporary_fullpath: &str, ftp_stream: &mut FtpStream)
+{
+	let temporary_name = get_file_name(temporary_fullpath);
+
+	for item in directory_listing
+	{
+		let entry_name = match list::File::from_str(item.as_str())
+		{
+			Ok(value) => value.name().to_string(),
+			Err(_) => continue,
+		};
+
+		if entry_name != temporary_name && is_temporary_file_of_same_file(&entry_name, temporary_name)
+		{
+			let _ = ftp_stream.rm(format!("{}/{}", remote_directory.trim_end_matches('/'), entry_name));
+		}
+	}
+}
+
+fn is_temporary_file(name: &str) -> bool
+{
+	name.starts_with('.') && name.ends_with(TEMPORARY_FILE_SUFFIX)
+}
+
+fn delete_from_local(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+
+	for linked_file in get_ordered_for_sync(all_linked_files, SyncVeredict::DeleteLocal)
+	{
+		if linked_file.sync_veredict == SyncVeredict::DeleteLocal
+		{
+			{ //Imprimir bonito
+				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+				let _ = queue!(stdout, SetForegroundColor(Color::Magenta));
+				let _ = queue!(stdout, Print("\nxx"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(" Deleting locally: "));
+				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+				let _ = queue!(stdout, Print(&linked_file.relative_path));
+				let _ = stdout.flush();
+			}
+
+			let (_, local_fullpath) = get_local_location(linked_file, sync_location);
+			let is_directory = is_linked_directory(linked_file);
+
+			//Directories are deleted after everything inside them, so anything still there was never synced (like excluded files) and is left alone
+			if is_directory && fs::read_dir(&local_fullpath).is_ok_and(|mut entries| entries.next().is_some())
+			{
+				let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+				let _ = queue!(stdout, Print(" (skipped!)"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = stdout.flush();
+				report.skipped.push((linked_file.relative_path.clone(), String::from("the directory has files that aren't synced, like excluded ones")));
+				continue;
+			}
+
+			let result = if is_directory
+			{
+				fs::remove_dir(&local_fullpath)
 			}
+			else
+			{
+				fs::remove_file(&local_fullpath)
+			};
 
-			//Preparar archivo local
-			let mut local_file_handler = match fs::OpenOptions::new().read(true).write(true).create(true).open(local_fullpath)
+			match result
 			{
-				Ok(value) => value,
+				Ok(_) => (),
 				Err(error) =>
 				{
 					report.errors += 1;
 					let _ = queue!(stdout, SetForegroundColor(Color::Red));
 					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
 					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to create local file or open existing local file as writable ({}), {}", &linked_file.relative_path, error)));
+					let _ = queue!(stdout, Print(format!("Failed to delete local {} ({}), {}", if is_directory { "directory" } else { "file" }, &local_fullpath, error)));
 					let _ = stdout.flush();
 
 					if !args.continue_on_error
 					{
-						std::process::exit(1);
+						return false;
 					}
 					continue;
 				}
-			};
+			}
 
-			//Cargar archivo remote
-			let remote_file = match &linked_file.remote_file
+			sync_state.files.remove(&linked_file.relative_path);
+			report.deleted_local += 1;
+			{ //Imprimir bonito
+				let _ = queue!(stdout, SetForegroundColor(Color::Green));
+				let _ = queue!(stdout, Print(" (done!)"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = stdout.flush();
+			}
+		}
+	}
+
+	true
+}
+
+fn delete_from_remote(all_linked_files: &Vec<LinkedFile>, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+
+	for linked_file in get_ordered_for_sync(all_linked_files, SyncVeredict::DeleteRemote)
+	{
+		if linked_file.sync_veredict == SyncVeredict::DeleteRemote
+		{
+			{ //Imprimir bonito
+				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+				let _ = queue!(stdout, SetForegroundColor(Color::Magenta));
+				let _ = queue!(stdout, Print("\nxx"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, Print(" Deleting on remote: "));
+				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+				let _ = queue!(stdout, Print(&linked_file.relative_path));
+				let _ = stdout.flush();
+			}
+
+			let remote_fullpath = format!("{}{}", sync_location.remote_path, linked_file.relative_path);
+			let is_directory = is_linked_directory(linked_file);
+
+			let result = if is_directory
 			{
-				Some(value) => value,
-				None =>
+				//Don't rely on RMD failing for directories with contents, some servers might not care.
+				//Like locally, what's left inside was never synced, and a server that refuses to delete it isn't a reason to stop the sync.
+				if is_remote_directory_empty(&remote_fullpath, sync_location, ftp_stream, server_features)
 				{
-					report.errors += 1;
-					let _ = queue!(stdout, SetForegroundColor(Color::Red));
-					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to access internal remote file handler! ({})", &linked_file.relative_path)));
-					let _ = stdout.flush();
-
-					if !args.continue_on_error
+					match retry_remote_command("Failed to delete remote directory", sync_location, ftp_stream, server_features, |ftp_stream, _| ftp_stream.rmdir(&remote_fullpath))
 					{
-						std::process::exit(1);
+						Ok(_) => Ok(None),
+						Err(error) if !ftp::is_transient_error(&error) => Ok(Some(format!("the server didn't delete it ({error})"))),
+						Err(error) => Err(error.to_string()),
 					}
-					continue;
 				}
+				else
+				{
+					Ok(Some(String::from("the directory has files that aren't synced, like excluded ones")))
+				}
+			}
+			else
+			{
+				retry_remote_command("Failed to delete remote file", sync_location, ftp_stream, server_features, |ftp_stream, _| ftp_stream.rm(&remote_fullpath)).map(|_| None).map_err(|error| error.to_string())
 			};
-			
-			let mut remote_file_handler = match ftp_stream.retr_as_stream(&remote_file.fullpath)
+
+			match result
 			{
-				Ok(value) => value,
+				Ok(None) => (),
+				Ok(Some(skip_reason)) =>
+				{
+					let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+					let _ = queue!(stdout, Print(" (skipped!)"));
+					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+					let _ = stdout.flush();
+					report.skipped.push((linked_file.relative_path.clone(), skip_reason));
+					continue;
+				},
 				Err(error) =>
 				{
 					report.errors += 1;
 					let _ = queue!(stdout, SetForegroundColor(Color::Red));
 					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
 					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Unable to retrieve remote file ({}), {}", &linked_file.relative_path, error)));
+					let _ = queue!(stdout, Print(format!("Failed to delete remote {} ({}), {}", if is_directory { "directory" } else { "file" }, &remote_fullpath, error)));
 					let _ = stdout.flush();
 
 					if !args.continue_on_error
 					{
-						std::process::exit(1);
+						return false;
 					}
 					continue;
 				}
-			};
+			}
+
+			sync_state.files.remove(&linked_file.relative_path);
+			report.deleted_remote += 1;
+			{ //Imprimir bonito
+				let _ = queue!(stdout, SetForegroundColor(Color::Green));
+				let _ = queue!(stdout, Print(" (done!)"));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = stdout.flush();
+			}
+		}
+	}
+
+	true
+}
+
+//Listed like any other directory, so lines that can't be understood (like the "total 0" some servers add) are skipped the same way
+fn is_remote_directory_empty(remote_directory: &str, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures) -> bool
+{
+	let directory = remote_directory.to_string();
+	match retry_remote_command("Failed to list remote directory", sync_location, ftp_stream, server_features, |ftp_stream, server_features| try_list_remote_directory(&directory, ftp_stream, server_features))
+	{
+		Ok(listing) => listing.files.iter().all(|file| file.name() == "." || file.name() == ".."),
+		Err(_) => false,
+	}
+}
+
+fn create_local_directory(linked_file: &LinkedFile, sync_location: &SyncLocation, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Blue));
+		let _ = queue!(stdout, Print("\n<-"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Creating locally: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	let remote_file = match &linked_file.remote_file
+	{
+		Some(value) => value,
+		None =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print("Somehow the directory to create doesn't exist on the remote!"));
+			let _ = stdout.flush();
+
+			if !args.continue_on_error
+			{
+				return false;
+			}
+			return true;
+		}
+	};
+
+	let (_, local_fullpath) = get_local_location(linked_file, sync_location);
+	match fs::create_dir_all(&local_fullpath)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to create local directory ({}), {}", &local_fullpath, error)));
+			let _ = stdout.flush();
+
+			if !args.continue_on_error
+			{
+				return false;
+			}
+			return true;
+		}
+	}
+
+	sync_state.files.insert
+	(
+		linked_file.relative_path.clone(),
+		SyncedFileState
+		{
+			local_date_modified: remote_file.date_modified.timestamp(),
+			remote_date_modified: remote_file.date_modified.timestamp(),
+			local_size: None,
+			remote_size: None,
+			hash: None,
+		}
+	);
+	report.downloaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+
+	true
+}
+
+//Parents are created before, see get_ordered_for_sync
+fn create_remote_directory(linked_file: &LinkedFile, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print("\n->"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Creating on remote: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	let local_file = match &linked_file.local_file
+	{
+		Some(value) => value,
+		None =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print("Somehow the directory to create doesn't exist locally!"));
+			let _ = stdout.flush();
+
+			if !args.continue_on_error
+			{
+				return false;
+			}
+			return true;
+		}
+	};
+
+	let remote_fullpath = format!("{}{}", sync_location.remote_path, linked_file.relative_path);
+	match retry_remote_command("Failed to create remote directory", sync_location, ftp_stream, server_features, |ftp_stream, _| ftp_stream.mkdir(&remote_fullpath))
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to create remote directory ({}), {}", &remote_fullpath, error)));
+			let _ = stdout.flush();
+
+			if !args.continue_on_error
+			{
+				return false;
+			}
+			return true;
+		}
+	}
+
+	sync_state.files.insert
+	(
+		linked_file.relative_path.clone(),
+		SyncedFileState
+		{
+			local_date_modified: local_file.date_modified.timestamp(),
+			remote_date_modified: local_file.date_modified.timestamp(),
+			local_size: None,
+			remote_size: None,
+			hash: None,
+		}
+	);
+	report.uploaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+
+	true
+}
+
+fn create_remote_symlink(linked_file: &LinkedFile, sync_location: &SyncLocation, ftp_stream: &mut FtpStream, server_features: &mut ServerFeatures, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print("\n->"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Linking on remote: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	let (local_file, symlink_target) = match &linked_file.local_file
+	{
+		Some(file) =>
+		{
+			match &file.symlink_target
+			{
+				Some(symlink_target) => (file, symlink_target),
+				None => return true,
+			}
+		},
+		None => return true,
+	};
+
+	let remote_fullpath = format!("{}{}", sync_location.remote_path, linked_file.relative_path);
+	match retry_remote_command("Failed to create remote link", sync_location, ftp_stream, server_features, |ftp_stream, server_features| ftp::create_remote_symlink(ftp_stream, server_features, symlink_target, &remote_fullpath))
+	{
+		Ok(true) => (),
+		Ok(false) =>
+		{
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print(" (skipped!)"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = stdout.flush();
+			report.skipped.push((linked_file.relative_path.clone(), String::from("the server doesn't support SITE SYMLINK")));
+			return true;
+		},
+		Err(error) =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to create remote link ({}), {}", &remote_fullpath, error)));
+			let _ = stdout.flush();
+
+			if !args.continue_on_error
+			{
+				return false;
+			}
+			return true;
+		}
+	}
+
+	sync_state.files.insert
+	(
+		linked_file.relative_path.clone(),
+		SyncedFileState
+		{
+			local_date_modified: local_file.date_modified.timestamp(),
+			remote_date_modified: local_file.date_modified.timestamp(),
+			local_size: None,
+			remote_size: None,
+			hash: None,
+		}
+	);
+	report.uploaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+
+	true
+}
+
+fn create_local_symlink(linked_file: &LinkedFile, sync_location: &SyncLocation, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
+{
+	let mut stdout = io::stdout();
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, SetForegroundColor(Color::Blue));
+		let _ = queue!(stdout, Print("\n<-"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = queue!(stdout, Print(" Linking locally: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(&linked_file.relative_path));
+		let _ = stdout.flush();
+	}
+
+	let (remote_file, symlink_target) = match &linked_file.remote_file
+	{
+		Some(file) =>
+		{
+			match &file.symlink_target
+			{
+				Some(symlink_target) => (file, symlink_target),
+				None => return true,
+			}
+		},
+		None => return true,
+	};
+
+	//MLSD doesn't always say where links point to
+	if symlink_target.is_empty()
+	{
+		let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+		let _ = queue!(stdout, Print(" (skipped!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+		report.skipped.push((linked_file.relative_path.clone(), String::from("the server didn't say where the link points to")));
+		return true;
+	}
+
+	let (_, local_fullpath) = get_local_location(linked_file, sync_location);
+
+	match make_local_symlink(symlink_target, &local_fullpath)
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			report.errors += 1;
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("Failed to create local link ({}), {}", &local_fullpath, error)));
+			let _ = stdout.flush();
 
-			//Descargar archivo local
-			match io::copy(&mut remote_file_handler, &mut local_file_handler)
+			if !args.continue_on_error
 			{
-				Ok(_) =>
-				{
-					match ftp_stream.finalize_retr_stream(remote_file_handler)
-					{
-						Ok(_) => (),
-						Err(error) =>
-						{
-							report.errors += 1;
-							let _ = queue!(stdout, SetForegroundColor(Color::Red));
-							let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-							let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-							let _ = queue!(stdout, Print(format!("Failed to finalize remote stream ({}), {}", &linked_file.relative_path, error)));
-							let _ = stdout.flush();
-
-							if !args.continue_on_error
-							{
-								std::process::exit(1);
-							}
-							continue;
-						}
-					}
-				},
-				Err(error) =>
-				{
-					report.errors += 1;
-					let _ = queue!(stdout, SetForegroundColor(Color::Red));
-					let _ = queue!(stdout, Print(" (failed!) \n[ERROR] "));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, Print(format!("Failed to download file ({}), {}", &linked_file.relative_path, error)));
-					let _ = stdout.flush();
-					
-					if !args.continue_on_error
-					{
-						std::process::exit(1);
-					}
-					continue;
-				}
+				return false;
 			}
+			return true;
+		}
+	}
 
-			report.downloaded += 1;
-			{ //Imprimir bonito
-				let _ = queue!(stdout, SetForegroundColor(Color::Green));
-				let _ = queue!(stdout, Print(" (done!)"));
-				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-				let _ = stdout.flush();
-			}
+	sync_state.files.insert
+	(
+		linked_file.relative_path.clone(),
+		SyncedFileState
+		{
+			local_date_modified: remote_file.date_modified.timestamp(),
+			remote_date_modified: remote_file.date_modified.timestamp(),
+			local_size: None,
+			remote_size: None,
+			hash: None,
 		}
+	);
+	report.downloaded += 1;
+	{ //Imprimir bonito
+		let _ = queue!(stdout, SetForegroundColor(Color::Green));
+		let _ = queue!(stdout, Print(" (done!)"));
+		let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+		let _ = stdout.flush();
+	}
+
+	true
+}
+
+//Windows needs to know if the link points to a directory, which is looked up from where the link is
+fn make_local_symlink(symlink_target: &str, link_fullpath: &str) -> io::Result<()>
+{
+	#[cfg(unix)]
+	let result = std::os::unix::fs::symlink(symlink_target, link_fullpath);
+	#[cfg(windows)]
+	let points_to_directory = match Path::new(link_fullpath).parent()
+	{
+		Some(link_directory) => link_directory.join(symlink_target).is_dir(),
+		None => false,
+	};
+	#[cfg(windows)]
+	let result = if points_to_directory
+	{
+		std::os::windows::fs::symlink_dir(symlink_target, link_fullpath)
 	}
+	else
+	{
+		std::os::windows::fs::symlink_file(symlink_target, link_fullpath)
+	};
+
+	result
 }
 
-fn do_nothing(all_linked_files: &Vec<LinkedFile>, report: &mut Report, args: &Args)
+fn do_nothing(all_linked_files: &Vec<LinkedFile>, sync_state: &mut SyncState, report: &mut Report, args: &Args) -> bool
 {
 	let mut stdout = io::stdout();
 
@@ -1279,6 +4553,29 @@ fn do_nothing(all_linked_files: &Vec<LinkedFile>, report: &mut Report, args: &Ar
 		if linked_file.sync_veredict == SyncVeredict::DoNothing
 		{
 			report.ignored += 1;
+			if let (Some(local_file), Some(remote_file)) = (&linked_file.local_file, &linked_file.remote_file)
+			{
+				let mut synced_file_state = SyncedFileState
+				{
+					local_date_modified: local_file.date_modified.timestamp(),
+					remote_date_modified: remote_file.date_modified.timestamp(),
+					local_size: if local_file.is_directory { None } else { Some(local_file.size) },
+					remote_size: if remote_file.is_directory { None } else { Some(remote_file.size) },
+					hash: None,
+				};
+
+				//If nothing changed since the last sync, neither did the content it hashed
+				if let Some(last_synced) = &linked_file.last_synced
+				{
+					synced_file_state.hash = last_synced.hash.clone();
+					if *last_synced != synced_file_state
+					{
+						synced_file_state.hash = None;
+					}
+				}
+
+				sync_state.files.insert(linked_file.relative_path.clone(), synced_file_state);
+			}
 			//Imprimir bonito
 			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
 			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
@@ -1286,11 +4583,34 @@ fn do_nothing(all_linked_files: &Vec<LinkedFile>, report: &mut Report, args: &Ar
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 			let _ = queue!(stdout, Print(" Ignoring: "));
 			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-			let _ = queue!(stdout, Print(format!("{}", linked_file.relative_path)));
+			let _ = queue!(stdout, Print(&linked_file.relative_path));
 			let _ = queue!(stdout, SetForegroundColor(Color::Green));
 			let _ = queue!(stdout, Print(" (done!)"));
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 		}
+		else if linked_file.sync_veredict == SyncVeredict::Skip
+		{
+			//Not remembered as synced either, so it's planned the same way next time
+			report.ignored += 1;
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Yellow));
+			let _ = queue!(stdout, Print("\n--"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(" Skipping: "));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, Print(&linked_file.relative_path));
+		}
+		else if linked_file.sync_veredict == SyncVeredict::Conflict
+		{
+			//Not remembered as synced, so it shows up as a conflict again next time
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, SetForegroundColor(Color::Red));
+			let _ = queue!(stdout, Print("\n!!"));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(" Skipping conflict: "));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, Print(&linked_file.relative_path));
+		}
 		else if linked_file.sync_veredict == SyncVeredict::NotDecidedYet
 		{
 			report.errors += 1;
@@ -1300,15 +4620,503 @@ fn do_nothing(all_linked_files: &Vec<LinkedFile>, report: &mut Report, args: &Ar
 			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
 			let _ = queue!(stdout, Print(" FAILED TO DETERMINE WHAT TO DO WITH THIS, WILL BE IGNORED: "));
 			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-			let _ = queue!(stdout, Print(format!("{}", linked_file.relative_path)));
+			let _ = queue!(stdout, Print(&linked_file.relative_path));
 
 			if !args.continue_on_error
 			{
 				let _ = stdout.flush();
-				std::process::exit(1);
+				return false;
 			}
 		}
 	}
 	let _ = stdout.flush();
 	std::thread::sleep(Duration::from_millis(300));
+
+	true
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const REMOTE_PATH: &str = "/data";
+	const LOCAL_PATH: &str = "/home/user/sync";
+
+	fn make_file(root: &str, relative_path: &str, is_directory: bool) -> File
+	{
+		let fullpath = format!("{root}{relative_path}");
+		let directory = match fullpath.rfind('/')
+		{
+			Some(index) => fullpath[..index].to_string(),
+			None => String::new(),
+		};
+
+		File
+		{
+			directory,
+			fullpath,
+			relative_path: relative_path.to_string(),
+			date_modified: DateTime::from_timestamp(1_700_000_000, 0).unwrap_or_default(),
+			date_is_exact: true,
+			size: if is_directory { 0 } else { 10 },
+			hash: None,
+			is_directory,
+			symlink_target: None,
+		}
+	}
+
+	//Paths ending in / are directories
+	fn link(remote: &[&str], local: &[&str], case_insensitive: bool, unicode_normalization: NormalizationForm) -> Vec<LinkedFile>
+	{
+		let remote_files = remote.iter().map(|path| make_file(REMOTE_PATH, path.trim_end_matches('/'), path.ends_with('/'))).collect();
+		let local_files = local.iter().map(|path| make_file(LOCAL_PATH, path.trim_end_matches('/'), path.ends_with('/'))).collect();
+		link_all_files(remote_files, local_files, &String::from(REMOTE_PATH), &String::from(LOCAL_PATH), &SyncState::new(), case_insensitive, unicode_normalization)
+	}
+
+	fn find<'a>(all_linked_files: &'a [LinkedFile], relative_path: &str) -> &'a LinkedFile
+	{
+		match all_linked_files.iter().find(|linked_file| linked_file.relative_path == relative_path)
+		{
+			Some(value) => value,
+			None => panic!("{relative_path} wasn't linked"),
+		}
+	}
+
+	fn is_case_collision(linked_file: &LinkedFile) -> bool
+	{
+		linked_file.sync_veredict == SyncVeredict::Conflict && linked_file.conflict_resolution == Some(ConflictResolution::CaseCollision)
+	}
+
+	#[test]
+	fn case_collision_on_the_same_side_is_a_conflict_in_both_modes()
+	{
+		for case_insensitive in [false, true]
+		{
+			let all_linked_files = link(&[], &["/A.txt", "/a.txt", "/b.txt"], case_insensitive, NormalizationForm::None);
+			assert!(is_case_collision(find(&all_linked_files, "/A.txt")));
+			assert!(is_case_collision(find(&all_linked_files, "/a.txt")));
+			assert_eq!(find(&all_linked_files, "/b.txt").sync_veredict, SyncVeredict::NotDecidedYet);
+		}
+	}
+
+	#[test]
+	fn case_only_rename_across_sides()
+	{
+		//Linked as the same file when case doesn't matter, keeping the remote name
+		let all_linked_files = link(&["/A.txt"], &["/a.txt"], true, NormalizationForm::None);
+		assert_eq!(all_linked_files.len(), 1);
+		let linked_file = find(&all_linked_files, "/A.txt");
+		assert!(linked_file.local_file.is_some() && linked_file.remote_file.is_some());
+		assert_eq!(linked_file.sync_veredict, SyncVeredict::NotDecidedYet);
+
+		//Two files that would replace each other when it does
+		let all_linked_files = link(&["/A.txt"], &["/a.txt"], false, NormalizationForm::None);
+		assert_eq!(all_linked_files.len(), 2);
+		assert!(is_case_collision(find(&all_linked_files, "/A.txt")));
+		assert!(is_case_collision(find(&all_linked_files, "/a.txt")));
+	}
+
+	#[test]
+	fn case_collision_between_a_directory_and_a_file()
+	{
+		let all_linked_files = link(&["/Docs/"], &["/docs"], false, NormalizationForm::None);
+		assert!(is_case_collision(find(&all_linked_files, "/Docs")));
+		assert!(is_case_collision(find(&all_linked_files, "/docs")));
+	}
+
+	#[test]
+	fn new_files_go_inside_the_directory_linked_on_the_other_side()
+	{
+		let all_linked_files = link(&["/Docs/", "/Docs/remote.txt", "/Docs/Sub/"], &["/docs/", "/docs/local.txt", "/docs/sub/", "/docs/sub/deep.txt"], true, NormalizationForm::None);
+		assert_eq!(all_linked_files.len(), 5);
+
+		//Uploaded into the remote's spelling instead of making a second directory
+		let uploaded = find(&all_linked_files, "/Docs/local.txt");
+		assert_eq!(uploaded.relative_directory, "/Docs");
+		assert_eq!(uploaded.local_relative_path, None);
+
+		let deep = find(&all_linked_files, "/Docs/Sub/deep.txt");
+		assert_eq!(deep.relative_directory, "/Docs/Sub");
+
+		//Downloaded into the local spelling
+		let downloaded = find(&all_linked_files, "/Docs/remote.txt");
+		assert_eq!(downloaded.local_relative_path, Some(String::from("/docs/remote.txt")));
+
+		//Without a match on the other side the names are left alone
+		let all_linked_files = link(&["/Docs/", "/Docs/remote.txt"], &["/docs/", "/docs/local.txt"], false, NormalizationForm::None);
+		assert_eq!(find(&all_linked_files, "/docs/local.txt").relative_directory, "/docs");
+		assert_eq!(find(&all_linked_files, "/Docs/remote.txt").local_relative_path, None);
+	}
+
+	#[test]
+	fn nfd_names_are_matched_against_nfc()
+	{
+		//Written like macOS on the local side and like Linux on the remote
+		let all_linked_files = link(&["/caf\u{e9}/", "/caf\u{e9}/remote.txt", "/r\u{e9}sum\u{e9}.txt"], &["/cafe\u{301}/", "/cafe\u{301}/local.txt", "/re\u{301}sume\u{301}.txt"], false, NormalizationForm::Nfc);
+		assert_eq!(all_linked_files.len(), 4);
+
+		let linked_file = find(&all_linked_files, "/r\u{e9}sum\u{e9}.txt");
+		assert!(linked_file.local_file.is_some() && linked_file.remote_file.is_some());
+
+		let directory = find(&all_linked_files, "/caf\u{e9}");
+		assert!(directory.local_file.is_some() && directory.remote_file.is_some());
+
+		let uploaded = find(&all_linked_files, "/caf\u{e9}/local.txt");
+		assert_eq!(uploaded.relative_directory, "/caf\u{e9}");
+
+		let downloaded = find(&all_linked_files, "/caf\u{e9}/remote.txt");
+		assert_eq!(downloaded.local_relative_path, Some(String::from("/cafe\u{301}/remote.txt")));
+
+		//Different files when names aren't normalized
+		let all_linked_files = link(&["/r\u{e9}sum\u{e9}.txt"], &["/re\u{301}sume\u{301}.txt"], false, NormalizationForm::None);
+		assert_eq!(all_linked_files.len(), 2);
+	}
+
+	const T: i64 = 1_700_000_000;
+
+	//Both sides are (date, size), None where the file is missing. The last sync is (local date, remote date) with a size of 10 on both sides.
+	fn make_linked_file(relative_path: &str, local: Option<(i64, u64)>, remote: Option<(i64, u64)>, last_synced: Option<(i64, i64)>) -> LinkedFile
+	{
+		let is_directory = relative_path.ends_with('/');
+		let relative_path = relative_path.trim_end_matches('/');
+		let make_side_file = |root: &str, (date, size): (i64, u64)| File
+		{
+			date_modified: DateTime::from_timestamp(date, 0).unwrap_or_default(),
+			size,
+			..make_file(root, relative_path, is_directory)
+		};
+
+		LinkedFile
+		{
+			relative_path: relative_path.to_string(),
+			relative_directory: get_parent_directory(relative_path).to_string(),
+			local_relative_path: None,
+			local_file: local.map(|side| make_side_file(LOCAL_PATH, side)),
+			remote_file: remote.map(|side| make_side_file(REMOTE_PATH, side)),
+			sync_veredict: SyncVeredict::NotDecidedYet,
+			last_synced: last_synced.map(|(local_date_modified, remote_date_modified)| SyncedFileState
+			{
+				local_date_modified,
+				remote_date_modified,
+				local_size: Some(10),
+				remote_size: Some(10),
+				hash: None,
+			}),
+			conflict_resolution: None,
+		}
+	}
+
+	fn get_test_args() -> Args
+	{
+		Args
+		{
+			wait_to_exit: false,
+			log_level: LogLevel::Default,
+			continue_on_error: true,
+			dry_run: false,
+			review: false,
+			upload_limit: None,
+			download_limit: None,
+		}
+	}
+
+	fn plan(linked_file: LinkedFile, compare_mode: CompareMode, direction: SyncDirection, listings_complete: bool) -> SyncVeredict
+	{
+		set_sync_veredicts(vec![linked_file], listings_complete, listings_complete, compare_mode, direction, &get_test_args())[0].sync_veredict
+	}
+
+	#[test]
+	fn three_way_decisions()
+	{
+		let synced = Some((T, T));
+		let cases =
+		[
+			//Never synced, the newest one wins
+			("same date, never synced", Some((T, 10)), Some((T, 10)), None, SyncVeredict::DoNothing),
+			("newer locally, never synced", Some((T + 60, 10)), Some((T, 10)), None, SyncVeredict::UploadToRemote),
+			("newer on the remote, never synced", Some((T, 10)), Some((T + 60, 10)), None, SyncVeredict::DownloadToLocal),
+			("only local, never synced", Some((T, 10)), None, None, SyncVeredict::UploadToRemote),
+			("only on the remote, never synced", None, Some((T, 10)), None, SyncVeredict::DownloadToLocal),
+
+			//Deletions
+			("deleted on the remote", Some((T, 10)), None, synced, SyncVeredict::DeleteLocal),
+			("deleted on the remote, changed locally", Some((T + 60, 10)), None, synced, SyncVeredict::UploadToRemote),
+			("deleted locally", None, Some((T, 10)), synced, SyncVeredict::DeleteRemote),
+			("deleted locally, changed on the remote", None, Some((T + 60, 10)), synced, SyncVeredict::DownloadToLocal),
+
+			//The side that changed wins, even with an older date
+			("changed locally", Some((T - 3600, 10)), Some((T, 10)), synced, SyncVeredict::UploadToRemote),
+			("changed on the remote", Some((T, 10)), Some((T - 3600, 10)), synced, SyncVeredict::DownloadToLocal),
+			("changed on both sides", Some((T + 60, 10)), Some((T + 120, 10)), synced, SyncVeredict::Conflict),
+			("different dates left by the last sync", Some((T, 10)), Some((T + 1, 10)), Some((T, T + 1)), SyncVeredict::DoNothing),
+		];
+
+		for (name, local, remote, last_synced, expected) in cases
+		{
+			let sync_veredict = plan(make_linked_file("/file.txt", local, remote, last_synced), CompareMode::Mtime, SyncDirection::Bidirectional, true);
+			assert_eq!(sync_veredict, expected, "{name}");
+		}
+	}
+
+	#[test]
+	fn nothing_is_deleted_when_a_listing_is_incomplete()
+	{
+		let synced = Some((T, T));
+		assert_eq!(plan(make_linked_file("/file.txt", Some((T, 10)), None, synced), CompareMode::Mtime, SyncDirection::Bidirectional, false), SyncVeredict::UploadToRemote);
+		assert_eq!(plan(make_linked_file("/file.txt", None, Some((T, 10)), synced), CompareMode::Mtime, SyncDirection::Bidirectional, false), SyncVeredict::DownloadToLocal);
+		assert_eq!(plan(make_linked_file("/dir/", Some((T, 0)), None, synced), CompareMode::Mtime, SyncDirection::Bidirectional, false), SyncVeredict::UploadToRemote);
+		assert_eq!(plan(make_linked_file("/file.txt", None, Some((T, 10)), synced), CompareMode::Mtime, SyncDirection::MirrorUp, false), SyncVeredict::DoNothing);
+	}
+
+	#[test]
+	fn compare_modes()
+	{
+		//Same date with a different size
+		let linked_file = || make_linked_file("/file.txt", Some((T, 10)), Some((T, 20)), Some((T, T)));
+		assert_eq!(plan(linked_file(), CompareMode::Mtime, SyncDirection::Bidirectional, true), SyncVeredict::DoNothing);
+		assert_eq!(plan(linked_file(), CompareMode::MtimeSize, SyncDirection::Bidirectional, true), SyncVeredict::DownloadToLocal);
+
+		//Different dates with the same content
+		let mut linked_file = make_linked_file("/file.txt", Some((T + 60, 10)), Some((T, 10)), None);
+		for file in [&mut linked_file.local_file, &mut linked_file.remote_file].into_iter().flatten()
+		{
+			file.hash = Some(HashAlgorithm::Md5.tag("0123456789abcdef0123456789abcdef"));
+		}
+		assert_eq!(plan(linked_file, CompareMode::Checksum, SyncDirection::Bidirectional, true), SyncVeredict::DoNothing);
+
+		//Same date with a different content
+		let mut linked_file = make_linked_file("/file.txt", Some((T, 10)), Some((T + 60, 10)), Some((T, T)));
+		if let Some(file) = &mut linked_file.local_file
+		{
+			file.hash = Some(HashAlgorithm::Md5.tag("0123456789abcdef0123456789abcdef"));
+		}
+		if let Some(file) = &mut linked_file.remote_file
+		{
+			file.hash = Some(HashAlgorithm::Md5.tag("fedcba9876543210fedcba9876543210"));
+		}
+		assert_eq!(plan(linked_file, CompareMode::Checksum, SyncDirection::Bidirectional, true), SyncVeredict::DownloadToLocal);
+	}
+
+	#[test]
+	fn directions()
+	{
+		let synced = Some((T, T));
+		let cases =
+		[
+			//Newer on the remote, deleted on the remote, deleted locally, changed on both sides
+			(SyncDirection::UploadOnly, [SyncVeredict::DoNothing, SyncVeredict::UploadToRemote, SyncVeredict::DoNothing, SyncVeredict::UploadToRemote]),
+			(SyncDirection::DownloadOnly, [SyncVeredict::DownloadToLocal, SyncVeredict::DoNothing, SyncVeredict::DownloadToLocal, SyncVeredict::DownloadToLocal]),
+			(SyncDirection::MirrorUp, [SyncVeredict::UploadToRemote, SyncVeredict::UploadToRemote, SyncVeredict::DeleteRemote, SyncVeredict::UploadToRemote]),
+			(SyncDirection::MirrorDown, [SyncVeredict::DownloadToLocal, SyncVeredict::DeleteLocal, SyncVeredict::DownloadToLocal, SyncVeredict::DownloadToLocal]),
+		];
+
+		for (direction, expected) in cases
+		{
+			let linked_files =
+			[
+				make_linked_file("/file.txt", Some((T, 10)), Some((T + 60, 10)), None),
+				make_linked_file("/file.txt", Some((T, 10)), None, synced),
+				make_linked_file("/file.txt", None, Some((T, 10)), synced),
+				make_linked_file("/file.txt", Some((T + 60, 10)), Some((T + 120, 10)), synced),
+			];
+
+			for (linked_file, expected) in linked_files.into_iter().zip(expected)
+			{
+				assert_eq!(plan(linked_file, CompareMode::Mtime, direction, true), expected, "{direction:?}");
+			}
+		}
+
+		//Mirrors leave alone what's already the same
+		assert_eq!(plan(make_linked_file("/file.txt", Some((T, 10)), Some((T, 10)), None), CompareMode::Mtime, SyncDirection::MirrorUp, true), SyncVeredict::DoNothing);
+		assert_eq!(plan(make_linked_file("/file.txt", Some((T, 10)), Some((T, 10)), None), CompareMode::Mtime, SyncDirection::MirrorDown, true), SyncVeredict::DoNothing);
+	}
+
+	#[test]
+	fn deleted_directories_with_new_files_inside_are_kept()
+	{
+		let synced = Some((T, T));
+		let all_linked_files = vec!
+		[
+			//Deleted on the remote, but something new was put inside locally
+			make_linked_file("/kept/", Some((T, 0)), None, synced),
+			make_linked_file("/kept/new.txt", Some((T, 10)), None, None),
+			//Deleted locally along with everything inside
+			make_linked_file("/gone/", None, Some((T, 0)), synced),
+			make_linked_file("/gone/old.txt", None, Some((T, 10)), synced),
+		];
+
+		let all_linked_files = set_sync_veredicts(all_linked_files, true, true, CompareMode::Mtime, SyncDirection::Bidirectional, &get_test_args());
+		assert_eq!(find(&all_linked_files, "/kept").sync_veredict, SyncVeredict::UploadToRemote);
+		assert_eq!(find(&all_linked_files, "/kept/new.txt").sync_veredict, SyncVeredict::UploadToRemote);
+		assert_eq!(find(&all_linked_files, "/gone").sync_veredict, SyncVeredict::DeleteRemote);
+		assert_eq!(find(&all_linked_files, "/gone/old.txt").sync_veredict, SyncVeredict::DeleteRemote);
+	}
+
+	#[test]
+	fn skipped_files_and_case_collisions_are_not_pending()
+	{
+		let mut all_linked_files = link(&["/A.txt", "/dir/"], &["/a.txt", "/dir", "/same.txt"], false, NormalizationForm::None);
+		for linked_file in &mut all_linked_files
+		{
+			if linked_file.sync_veredict == SyncVeredict::NotDecidedYet
+			{
+				linked_file.sync_veredict = if linked_file.relative_path == "/dir" { SyncVeredict::Skip } else { SyncVeredict::DoNothing };
+			}
+		}
+		assert!(!has_changes_pending(&all_linked_files));
+
+		all_linked_files.push(make_linked_file("/conflict.txt", Some((T + 60, 10)), Some((T + 120, 10)), Some((T, T))));
+		all_linked_files = set_sync_veredicts(all_linked_files, true, true, CompareMode::Mtime, SyncDirection::Bidirectional, &get_test_args());
+		assert!(has_changes_pending(&all_linked_files));
+	}
+
+	#[test]
+	fn directories_flipped_to_be_deleted_keep_what_is_inside()
+	{
+		//Like after a review that flipped the directories but not what's inside
+		let mut all_linked_files = link(&["/new/", "/new/file.txt", "/old/", "/old/file.txt"], &["/new/", "/new/file.txt", "/old/", "/old/file.txt"], false, NormalizationForm::None);
+		for linked_file in &mut all_linked_files
+		{
+			linked_file.sync_veredict = match linked_file.relative_path.as_str()
+			{
+				"/new" => SyncVeredict::DeleteLocal,
+				"/new/file.txt" => SyncVeredict::UploadToRemote,
+				"/old" => SyncVeredict::DeleteRemote,
+				_ => SyncVeredict::Skip,
+			};
+		}
+
+		let all_linked_files = keep_directories_with_contents(all_linked_files);
+		assert_eq!(find(&all_linked_files, "/new").sync_veredict, SyncVeredict::UploadToRemote);
+		assert_eq!(find(&all_linked_files, "/old").sync_veredict, SyncVeredict::DownloadToLocal);
+	}
+
+	//Plans a made up tree of 200k entries on each side (files spread over directories, some only on one side, some changed, some deleted)
+	//and times each step. Only meaningful on a release build: cargo test --release -- --ignored --nocapture
+	#[test]
+	#[ignore]
+	fn planning_200k_entries_takes_less_than_a_second()
+	{
+		const ENTRIES: usize = 200_000;
+		const ENTRIES_PER_DIRECTORY: usize = 200;
+
+		let args = Args
+		{
+			wait_to_exit: false,
+			log_level: LogLevel::Default,
+			continue_on_error: true,
+			dry_run: false,
+			review: false,
+			upload_limit: None,
+			download_limit: None,
+		};
+
+		let remote_path = String::from("/benchmark");
+		let local_path = String::from("/tmp/benchmark");
+		let date_modified = Utc::now().with_nanosecond(0).unwrap_or(Utc::now());
+
+		let mut all_remote_files: Vec<File> = Vec::with_capacity(ENTRIES);
+		let mut all_local_files: Vec<File> = Vec::with_capacity(ENTRIES);
+		let mut sync_state = SyncState::new();
+
+		let mut i: usize = 0;
+		while all_remote_files.len() < ENTRIES || all_local_files.len() < ENTRIES
+		{
+			let is_directory = i.is_multiple_of(ENTRIES_PER_DIRECTORY);
+			let relative_directory = format!("/d{}", i / ENTRIES_PER_DIRECTORY);
+			let relative_path = if is_directory
+			{
+				relative_directory.clone()
+			}
+			else
+			{
+				format!("{relative_directory}/f{i}.txt")
+			};
+
+			//Most files are in sync, the rest are spread between being new, changed or deleted on either side
+			let (on_remote, on_local, synced_before, changed_locally) = match i % 20
+			{
+				_ if is_directory => (true, true, true, false),
+				1 => (false, true, false, false),
+				2 => (true, false, false, false),
+				3 => (true, true, true, true),
+				4 => (true, false, true, false),
+				5 => (false, true, true, false),
+				_ => (true, true, true, false),
+			};
+
+			let make_file = |root: &String, date_modified: DateTime<Utc>| File
+			{
+				directory: if is_directory { root.clone() } else { format!("{root}{relative_directory}") },
+				fullpath: format!("{root}{relative_path}"),
+				relative_path: relative_path.clone(),
+				date_modified,
+				date_is_exact: true,
+				size: if is_directory { 0 } else { i as u64 },
+				hash: None,
+				is_directory,
+				symlink_target: None,
+			};
+
+			if on_remote
+			{
+				all_remote_files.push(make_file(&remote_path, date_modified));
+			}
+			if on_local
+			{
+				all_local_files.push(make_file(&local_path, if changed_locally { date_modified + chrono::Duration::seconds(60) } else { date_modified }));
+			}
+			if synced_before
+			{
+				sync_state.files.insert
+				(
+					relative_path.clone(),
+					SyncedFileState
+					{
+						local_date_modified: date_modified.timestamp(),
+						remote_date_modified: date_modified.timestamp(),
+						local_size: if is_directory { None } else { Some(i as u64) },
+						remote_size: if is_directory { None } else { Some(i as u64) },
+						hash: None,
+					}
+				);
+			}
+
+			i += 1;
+		}
+
+		let remote_entries = all_remote_files.len();
+		let local_entries = all_local_files.len();
+
+		let start = std::time::Instant::now();
+		let all_linked_files = link_all_files(all_remote_files, all_local_files, &remote_path, &local_path, &sync_state, false, NormalizationForm::Nfc);
+		let linking_time = start.elapsed();
+
+		let start = std::time::Instant::now();
+		let all_linked_files = set_sync_veredicts(all_linked_files, true, true, CompareMode::MtimeSize, SyncDirection::Bidirectional, &args);
+		let deciding_time = start.elapsed();
+
+		let start = std::time::Instant::now();
+		let mut planned = 0;
+		for sync_veredict in [SyncVeredict::UploadToRemote, SyncVeredict::DownloadToLocal, SyncVeredict::DeleteLocal, SyncVeredict::DeleteRemote]
+		{
+			planned += get_ordered_for_sync(&all_linked_files, sync_veredict).len();
+		}
+		let ordering_time = start.elapsed();
+
+		let total_time = linking_time + deciding_time + ordering_time;
+		println!("{remote_entries} remote and {local_entries} local entries, {} linked, {planned} to sync.", all_linked_files.len());
+		println!("Linking: {} ms\nDeciding: {} ms\nOrdering: {} ms\nTotal: {} ms", linking_time.as_millis(), deciding_time.as_millis(), ordering_time.as_millis(), total_time.as_millis());
+		assert!(total_time < Duration::from_secs(1));
+	}
+
+	#[test]
+	fn remote_host_names_for_conflict_copies()
+	{
+		assert_eq!(get_remote_host_name("ftp.example.com:21"), "ftp_example_com");
+		assert_eq!(get_remote_host_name("192.168.1.20:2121"), "192_168_1_20");
+		assert_eq!(get_remote_host_name("[::1]:21"), "__1");
+		assert_eq!(get_remote_host_name("nas"), "nas");
+	}
 }
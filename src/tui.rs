@@ -29,6 +29,8 @@ use crossterm::
 	}
 };
 
+use crate::config::SyncDirection;
+
 #[derive(Clone)]
 enum TuiState
 {
@@ -51,6 +53,7 @@ enum AddRemoteTuiStep
 	SettingRemotePassword,
 	BasicSummary,
 	AdvancedBackup,
+	AdvancedDirection,
 }
 
 enum UserInput
@@ -82,6 +85,7 @@ pub struct NewRemoteDetails
 	pub remote_username: Option<String>,
 	pub remote_password: Option<String>,
 	pub advanced_backups: Option<bool>,
+	pub direction: Option<SyncDirection>,
 }
 
 impl NewRemoteDetails
@@ -97,6 +101,7 @@ impl NewRemoteDetails
 			remote_username: None,
 			remote_password: None,
 			advanced_backups: None,
+			direction: None,
 		}
 	}
 }
@@ -107,7 +112,7 @@ pub enum TuiResult
 	CreateRemote(NewRemoteDetails),
 }
 
-pub fn start_tui_blocking(selectable_options: &Vec<String>) -> TuiResult
+pub fn start_tui_blocking(selectable_options: &[String]) -> TuiResult
 {
 	let _ = execute!(stdout(), EnterAlternateScreen);
 	let _ = execute!(stdout(), Hide);
@@ -124,7 +129,7 @@ pub fn start_tui_blocking(selectable_options: &Vec<String>) -> TuiResult
 			TuiState::MainMenu(_) =>
 			{
 				render_main_menu(&ui_state, selectable_options);
-				ui_state = logic_main_menu(&mut ui_state, selectable_options);
+				ui_state = logic_main_menu(&ui_state, selectable_options);
 			},
 			TuiState::RemoteSelected(value) =>
 			{
@@ -134,7 +139,7 @@ pub fn start_tui_blocking(selectable_options: &Vec<String>) -> TuiResult
 			TuiState::AddRemote(_) =>
 			{
 				//render_add_remote_menu(&ui_state); //this should be called by logic_add_remote only once per screen
-				ui_state = logic_add_remote_menu(&mut ui_state, &mut new_remote_details);
+				ui_state = logic_add_remote_menu(&ui_state, &mut new_remote_details);
 			},
 			TuiState::AddRemoteDone =>
 			{
@@ -145,7 +150,7 @@ pub fn start_tui_blocking(selectable_options: &Vec<String>) -> TuiResult
 	}
 }
 
-fn logic_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>) -> TuiState
+fn logic_main_menu(ui_state: &TuiState, selectable_options: &[String]) -> TuiState
 {
 	match is_raw_mode_enabled()
 	{
@@ -173,7 +178,7 @@ fn logic_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>) -> Tui
 
 	let mut index_selected_option = match ui_state
 	{
-		TuiState::MainMenu(value) => value.clone(),
+		TuiState::MainMenu(value) => *value,
 		_ =>
 		{
 			panic_gracefully("[ERROR] logic_main_menu should not be called if current menu is not MainMenu!!");
@@ -193,10 +198,7 @@ fn logic_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>) -> Tui
 		},
 		UserInput::MoveUp =>
 		{
-			if index_selected_option > 0
-			{
-				index_selected_option -= 1;
-			}
+			index_selected_option = index_selected_option.saturating_sub(1);
 		},
 		UserInput::Select =>
 		{
@@ -219,11 +221,11 @@ fn logic_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>) -> Tui
 	TuiState::MainMenu(index_selected_option)
 }
 
-fn render_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>)
+fn render_main_menu(ui_state: &TuiState, selectable_options: &[String])
 {
 	let selected_option = match ui_state
 	{
-		TuiState::MainMenu(value) => value.clone(),
+		TuiState::MainMenu(value) => *value,
 		_ =>
 		{
 			panic_gracefully("[ERROR] render_main_menu should not be called if current menu is not MainMenu!!");
@@ -234,9 +236,9 @@ fn render_main_menu(ui_state: &TuiState, selectable_options: &Vec<String>)
 	let redraw_options = RedrawOptions
 	{
 		box_title: String::from(" Select a remote to sync "),
-		selectable_options: Some(selectable_options.clone()),
+		selectable_options: Some(selectable_options.to_vec()),
 		draw_options_at_coordinates: (0, 0),
-		selected_option: selected_option,
+		selected_option,
 	};
 
 	redraw(&redraw_options);
@@ -333,7 +335,8 @@ fn logic_add_remote_menu(ui_state: &TuiState, new_remote_details: &mut NewRemote
 				},
 				AddRemoteTuiStep::AskingIfNeedsLogin |
 				AddRemoteTuiStep::BasicSummary |
-				AddRemoteTuiStep::AdvancedBackup =>
+				AddRemoteTuiStep::AdvancedBackup |
+				AddRemoteTuiStep::AdvancedDirection =>
 				{
 					let _ = execute!(stdout(), Hide);
 					let mut index_selected_option = 0;
@@ -343,6 +346,7 @@ fn logic_add_remote_menu(ui_state: &TuiState, new_remote_details: &mut NewRemote
 						AddRemoteTuiStep::AskingIfNeedsLogin => 2,
 						AddRemoteTuiStep::BasicSummary => 3,
 						AddRemoteTuiStep::AdvancedBackup => 2,
+						AddRemoteTuiStep::AdvancedDirection => 5,
 						_ => 0,
 					};
 					
@@ -361,10 +365,7 @@ fn logic_add_remote_menu(ui_state: &TuiState, new_remote_details: &mut NewRemote
 							},
 							UserInput::MoveUp =>
 							{
-								if index_selected_option > 0
-								{
-									index_selected_option -= 1;
-								}
+								index_selected_option = index_selected_option.saturating_sub(1);
 							},
 							UserInput::Select =>
 							{
@@ -403,6 +404,7 @@ fn logic_add_remote_menu(ui_state: &TuiState, new_remote_details: &mut NewRemote
 								{
 									//Set all advanced settings to their default state
 									new_remote_details.advanced_backups = Some(true);
+									new_remote_details.direction = Some(SyncDirection::Bidirectional);
 
 									TuiState::AddRemoteDone
 								},
@@ -426,13 +428,24 @@ fn logic_add_remote_menu(ui_state: &TuiState, new_remote_details: &mut NewRemote
 							if index_selected_option == 0
 							{
 								new_remote_details.advanced_backups = Some(true);
-								TuiState::AddRemoteDone //Esto debería de cambiarse a un AdvancedSummary o algo luego.
 							}
 							else
 							{
 								new_remote_details.advanced_backups = Some(false);
-								TuiState::AddRemoteDone //Esto debería de cambiarse a un AdvancedSummary o algo luego.
 							}
+							TuiState::AddRemote(AddRemoteTuiStep::AdvancedDirection)
+						},
+						AddRemoteTuiStep::AdvancedDirection =>
+						{
+							new_remote_details.direction = match index_selected_option
+							{
+								1 => Some(SyncDirection::UploadOnly),
+								2 => Some(SyncDirection::DownloadOnly),
+								3 => Some(SyncDirection::MirrorUp),
+								4 => Some(SyncDirection::MirrorDown),
+								_ => Some(SyncDirection::Bidirectional),
+							};
+							TuiState::AddRemoteDone //Esto debería de cambiarse a un AdvancedSummary o algo luego.
 						},
 						_ => ui_state.clone()
 					}
@@ -626,7 +639,7 @@ fn render_add_remote_menu(ui_state: &TuiState, current_string: &str, selected_op
 							box_title: String::from(" Add remote "),
 							selectable_options: Some(selectable_options),
 							draw_options_at_coordinates: (2, 0),
-							selected_option: selected_option,
+							selected_option,
 						}
 					);
 
@@ -770,7 +783,7 @@ fn render_add_remote_menu(ui_state: &TuiState, current_string: &str, selected_op
 							box_title: String::from(" Add remote "),
 							selectable_options: Some(selectable_options),
 							draw_options_at_coordinates: (12, 0),
-							selected_option: selected_option,
+							selected_option,
 						}
 					);
 
@@ -840,7 +853,7 @@ fn render_add_remote_menu(ui_state: &TuiState, current_string: &str, selected_op
 							box_title: String::from(" Add remote with advanced settings "),
 							selectable_options: Some(selectable_options),
 							draw_options_at_coordinates: (6, 0),
-							selected_option: selected_option,
+							selected_option,
 						}
 					);
 					let _ = queue!(stdout, MoveTo(3, 2));
@@ -861,6 +874,48 @@ fn render_add_remote_menu(ui_state: &TuiState, current_string: &str, selected_op
 					let _ = queue!(stdout, MoveToNextLine(2));
 					let _ = queue!(stdout, MoveToColumn(3));
 					let _ = stdout.flush();
+				},
+				AddRemoteTuiStep::AdvancedDirection =>
+				{
+					let selectable_options = vec!
+					[
+						String::from("Both ways (default)"),
+						String::from("Upload only"),
+						String::from("Download only"),
+						String::from("Mirror local to remote"),
+						String::from("Mirror remote to local"),
+					];
+					redraw
+					(
+						&RedrawOptions
+						{
+							box_title: String::from(" Add remote with advanced settings "),
+							selectable_options: Some(selectable_options),
+							draw_options_at_coordinates: (9, 0),
+							selected_option,
+						}
+					);
+					let _ = queue!(stdout, MoveTo(3, 2));
+					let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+					let _ = queue!(stdout, SetForegroundColor(Color::DarkYellow));
+					let _ = queue!(stdout, Print("Direction"));
+					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+					let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+					let _ = queue!(stdout, MoveToNextLine(1));
+					let _ = queue!(stdout, MoveToColumn(4));
+					let _ = queue!(stdout, Print("Which way should changes go?"));
+					let _ = queue!(stdout, MoveToNextLine(2));
+					let _ = queue!(stdout, MoveToColumn(4));
+					let _ = queue!(stdout, Print("Upload and download only copy files, they never delete anything."));
+					let _ = queue!(stdout, MoveToNextLine(1));
+					let _ = queue!(stdout, MoveToColumn(4));
+					let _ = queue!(stdout, Print("Mirroring makes the other side identical, deleting whatever isn't on this side."));
+					let _ = queue!(stdout, MoveToNextLine(1));
+					let _ = queue!(stdout, MoveToColumn(4));
+					let _ = queue!(stdout, Print("Changes made on the other side are never synced back."));
+					let _ = queue!(stdout, MoveToNextLine(2));
+					let _ = queue!(stdout, MoveToColumn(3));
+					let _ = stdout.flush();
 				}
 				/*
 				_ =>
@@ -907,7 +962,7 @@ fn redraw(redraw_options: &RedrawOptions)
 }
 
 
-fn draw_box(title: &String, width: u16, height: u16, mut stdout: Stdout) -> Stdout
+fn draw_box(title: &str, width: u16, height: u16, mut stdout: Stdout) -> Stdout
 {
 	//Draw top horizontal line
 	let mut line =  String::with_capacity(width as usize);
@@ -922,9 +977,9 @@ fn draw_box(title: &String, width: u16, height: u16, mut stdout: Stdout) -> Stdo
 	{
 		if !title_drawn && i > 4
 		{
-			line.push_str("\u{2563}");
-			line.push_str(title.as_str());
-			line.push_str("\u{2560}");
+			line.push('\u{2563}');
+			line.push_str(title);
+			line.push('\u{2560}');
 			i += title.len() as u16 + 2;
 			title_drawn = true;
 		}
@@ -993,37 +1048,29 @@ fn draw_box(title: &String, width: u16, height: u16, mut stdout: Stdout) -> Stdo
 
 fn draw_options(redraw_options: &RedrawOptions, mut stdout: Stdout) -> Stdout
 {
-	match &redraw_options.selectable_options
+	if let Some(selectable_options) = &redraw_options.selectable_options
 	{
-		Some(selectable_options) =>
-		{
-			let mut row: u16 = 2 + redraw_options.draw_options_at_coordinates.0;
-			let column: u16 = 3 + redraw_options.draw_options_at_coordinates.1;
-			let mut i = 0;
+		let first_row: u16 = 2 + redraw_options.draw_options_at_coordinates.0;
+		let column: u16 = 3 + redraw_options.draw_options_at_coordinates.1;
 
-			for item in selectable_options
+		for (i, item) in selectable_options.iter().enumerate()
+		{
+			let _ = queue!(stdout, MoveTo(column, first_row + i as u16));
+			if redraw_options.selected_option != i
 			{
-				let _ = queue!(stdout, MoveTo(column, row));
-				if redraw_options.selected_option != i
-				{
-					let _ = queue!(stdout, Print(format!(" -  {item}" )));
-				}
-				else
-				{
-					let _ = queue!(stdout, SetBackgroundColor(Color::Yellow));
-					let _ = queue!(stdout, SetForegroundColor(Color::Black));
-					let _ = queue!(stdout, SetAttribute(Attribute::Bold));
-					let _ = queue!(stdout, Print(format!(" -> {item} ")));
-					let _ = queue!(stdout, SetAttribute(Attribute::Reset));
-					let _ = queue!(stdout, SetForegroundColor(Color::Reset));
-					let _ = queue!(stdout, SetBackgroundColor(Color::Reset));
-				}
-				
-				i += 1;
-				row += 1;
+				let _ = queue!(stdout, Print(format!(" -  {item}" )));
 			}
-		},
-		None => (),
+			else
+			{
+				let _ = queue!(stdout, SetBackgroundColor(Color::Yellow));
+				let _ = queue!(stdout, SetForegroundColor(Color::Black));
+				let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+				let _ = queue!(stdout, Print(format!(" -> {item} ")));
+				let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+				let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+				let _ = queue!(stdout, SetBackgroundColor(Color::Reset));
+			}
+		}
 	}
 
 	stdout
@@ -1033,19 +1080,8 @@ fn process_input_raw_mode(event: &Event, return_chars: bool) -> UserInput
 {
 	let key_event = match event.as_key_event()
 	{
-		Some(value) =>
-		{
-			if value.kind == KeyEventKind::Press
-			|| value.kind == KeyEventKind::Repeat
-			{
-				value
-			}
-			else
-			{
-				return UserInput::Ignore;
-			}
-		},
-		None =>
+		Some(value) if value.kind == KeyEventKind::Press || value.kind == KeyEventKind::Repeat => value,
+		_ =>
 		{
 			return UserInput::Ignore;
 		}
@@ -1062,20 +1098,16 @@ fn process_input_raw_mode(event: &Event, return_chars: bool) -> UserInput
 	}
 
 	if key_event.modifiers == KeyModifiers::CONTROL
+	&& (key_event.code == KeyCode::Char('c') || key_event.code == KeyCode::Char('q'))
 	{
-		if key_event.code == KeyCode::Char('c')
-		|| key_event.code == KeyCode::Char('q')
-		{
-			return UserInput::Exit;
-		}
+		return UserInput::Exit;
 	}
 
 	if return_chars
 	{
-		match key_event.code.as_char()
+		if let Some(value) = key_event.code.as_char()
 		{
-			Some(value) => return UserInput::Char(value),
-			None => (),
+			return UserInput::Char(value);
 		}
 	}
 
@@ -1122,3 +1154,228 @@ fn panic_gracefully(message: &str)
 	reset_terminal();
 	panic!("{}", message);
 }
+
+//What the plan review screen can do with each file, sync.rs translates it from and to its own veredicts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReviewAction
+{
+	Upload,
+	Download,
+	DeleteLocal,
+	DeleteRemote,
+	KeepBoth,
+	Conflict, //Left to the conflict policy
+	Skip,
+}
+
+pub struct ReviewItem
+{
+	pub relative_path: String,
+	pub action: ReviewAction,
+	pub local_description: Option<String>, //None if the file doesn't exist on that side
+	pub remote_description: Option<String>,
+}
+
+//Lets the user change what will be done with each file before syncing, returns the action chosen for every item in the same order.
+pub fn start_plan_review_blocking(review_items: &Vec<ReviewItem>) -> Vec<ReviewAction>
+{
+	let _ = execute!(stdout(), EnterAlternateScreen);
+	let _ = execute!(stdout(), Hide);
+	match enable_raw_mode()
+	{
+		Ok(_) => (),
+		Err(error) =>
+		{
+			panic_gracefully(format!("[ERROR] Failed to activate terminal raw mode!! {}", error).as_str());
+			std::process::exit(1);
+		}
+	}
+
+	let mut actions: Vec<ReviewAction> = Vec::with_capacity(review_items.len());
+	for item in review_items
+	{
+		actions.push(item.action);
+	}
+
+	let mut selected_item: usize = 0;
+	loop
+	{
+		render_plan_review(review_items, &actions, selected_item);
+
+		match read_input_raw_mode(true)
+		{
+			UserInput::MoveDown if selected_item + 1 < review_items.len() =>
+			{
+				selected_item += 1;
+			},
+			UserInput::MoveUp =>
+			{
+				selected_item = selected_item.saturating_sub(1);
+			},
+			UserInput::Char('f') =>
+			{
+				if let Some(item) = review_items.get(selected_item)
+				{
+					actions[selected_item] = get_flipped_review_action(item, actions[selected_item]);
+				}
+			},
+			UserInput::Char('s') =>
+			{
+				if let Some(item) = review_items.get(selected_item)
+				{
+					//Skipping twice brings back what was planned
+					actions[selected_item] = if actions[selected_item] == ReviewAction::Skip { item.action } else { ReviewAction::Skip };
+				}
+			},
+			UserInput::Char('a') | UserInput::Select =>
+			{
+				break;
+			},
+			UserInput::Exit =>
+			{
+				kill_program();
+			},
+			_ => (),
+		}
+	}
+
+	reset_terminal();
+	actions
+}
+
+//Files on both sides switch between uploading and downloading, files on one side between copying them to the other side and deleting them.
+fn get_flipped_review_action(review_item: &ReviewItem, action: ReviewAction) -> ReviewAction
+{
+	match (&review_item.local_description, &review_item.remote_description)
+	{
+		(Some(_), Some(_)) =>
+		{
+			match action
+			{
+				ReviewAction::Upload => ReviewAction::Download,
+				_ => ReviewAction::Upload,
+			}
+		},
+		(Some(_), None) =>
+		{
+			match action
+			{
+				ReviewAction::Upload => ReviewAction::DeleteLocal,
+				_ => ReviewAction::Upload,
+			}
+		},
+		(None, Some(_)) =>
+		{
+			match action
+			{
+				ReviewAction::Download => ReviewAction::DeleteRemote,
+				_ => ReviewAction::Download,
+			}
+		},
+		(None, None) => action,
+	}
+}
+
+fn get_review_action_label(action: ReviewAction) -> (&'static str, Color)
+{
+	match action
+	{
+		ReviewAction::Upload => ("-> upload       ", Color::Green),
+		ReviewAction::Download => ("<- download     ", Color::Blue),
+		ReviewAction::DeleteLocal => ("xx delete local ", Color::Magenta),
+		ReviewAction::DeleteRemote => ("xx delete remote", Color::Magenta),
+		ReviewAction::KeepBoth => ("<> keep both    ", Color::Cyan),
+		ReviewAction::Conflict => ("!! conflict     ", Color::Red),
+		ReviewAction::Skip => ("-- skip         ", Color::Yellow),
+	}
+}
+
+fn render_plan_review(review_items: &[ReviewItem], actions: &[ReviewAction], selected_item: usize)
+{
+	let mut stdout = stdout();
+
+	redraw
+	(
+		&RedrawOptions
+		{
+			box_title: format!(" Review sync plan ({} files) ", review_items.len()),
+			selectable_options: None,
+			draw_options_at_coordinates: (0, 0),
+			selected_option: 0,
+		}
+	);
+
+	let (width, height) = match size()
+	{
+		Ok(value) => value,
+		Err(_) => return,
+	};
+	let max_text_width = (width as usize).saturating_sub(26);
+
+	let _ = queue!(stdout, MoveTo(3, 2));
+	let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+	let _ = queue!(stdout, SetForegroundColor(Color::DarkYellow));
+	let _ = queue!(stdout, Print("[f] flip direction  [s] skip  [a/Enter] accept and sync  [Esc] cancel"));
+	let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+	let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+
+	//Rows left for the list after the help line on top and the details of the selected file at the bottom
+	let first_row: u16 = 4;
+	let visible_rows = (height as usize).saturating_sub(10).max(1);
+	let first_item = if selected_item >= visible_rows { selected_item + 1 - visible_rows } else { 0 };
+
+	for (row, index) in (first_item..review_items.len()).take(visible_rows).enumerate()
+	{
+		let (label, color) = get_review_action_label(actions[index]);
+		let relative_path: String = review_items[index].relative_path.chars().take(max_text_width).collect();
+
+		let _ = queue!(stdout, MoveTo(3, first_row + row as u16));
+		if index == selected_item
+		{
+			let _ = queue!(stdout, SetBackgroundColor(Color::Yellow));
+			let _ = queue!(stdout, SetForegroundColor(Color::Black));
+			let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+			let _ = queue!(stdout, Print(format!(" {label}  {relative_path} ")));
+			let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, SetBackgroundColor(Color::Reset));
+		}
+		else
+		{
+			let _ = queue!(stdout, SetForegroundColor(color));
+			let _ = queue!(stdout, Print(format!(" {label}")));
+			let _ = queue!(stdout, SetForegroundColor(Color::Reset));
+			let _ = queue!(stdout, Print(format!("  {relative_path}")));
+		}
+	}
+
+	if let Some(item) = review_items.get(selected_item)
+	{
+		let details_row = height.saturating_sub(5);
+		let not_existing = String::from("(doesn't exist)");
+		let local_description = match &item.local_description
+		{
+			Some(value) => value,
+			None => &not_existing,
+		};
+		let remote_description = match &item.remote_description
+		{
+			Some(value) => value,
+			None => &not_existing,
+		};
+
+		let _ = queue!(stdout, MoveTo(4, details_row));
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, Print("Local:  "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(local_description));
+		let _ = queue!(stdout, MoveTo(4, details_row + 1));
+		let _ = queue!(stdout, SetAttribute(Attribute::Bold));
+		let _ = queue!(stdout, Print("Remote: "));
+		let _ = queue!(stdout, SetAttribute(Attribute::Reset));
+		let _ = queue!(stdout, Print(remote_description));
+	}
+
+	let _ = queue!(stdout, MoveTo(0, height));
+	let _ = stdout.flush();
+}